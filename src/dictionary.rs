@@ -0,0 +1,504 @@
+use std::{
+    path::{Path, PathBuf},
+    sync::OnceLock,
+};
+
+use crate::export::{self, Export};
+use crate::fulltext::{self, Hit, Index};
+use crate::fuzzy::{BkTree, Distance};
+use crate::lemma;
+use crate::mdict::{
+    mdd::Mdd,
+    mdx::{self, Mdx},
+    DictMeta, Error, ParseOptions, Result, Source,
+};
+use crate::pattern::Pattern;
+use crate::reverse::ReverseIndex;
+
+/// 查询得到的一个词条
+#[derive(Debug, Clone, PartialEq)]
+pub struct Entry {
+    pub key: String,
+    pub definition: String,
+    /// 经过 `@@@LINK=` 跳转到这个词条时, 依次经过的词头
+    pub redirects: Vec<String>,
+    /// 查询的词不存在, 通过词形还原找到这个词条时使用的原形
+    pub lemma: Option<String>,
+}
+
+impl Entry {
+    /// 跳转的过程, 如 `went → go`
+    pub fn chain(&self) -> String {
+        self.redirects
+            .iter()
+            .chain([&self.key])
+            .map(|v| v.as_str())
+            .collect::<Vec<_>>()
+            .join(" → ")
+    }
+}
+
+const LINK: &str = "@@@LINK=";
+
+/// 默认最多跳转的次数
+const MAX_REDIRECTS: usize = 8;
+
+fn link(definition: &str) -> Option<&str> {
+    definition.trim().strip_prefix(LINK).map(|v| v.trim())
+}
+
+/// 一部 mdx 词典
+///
+/// ```no_run
+/// let dict = mdict_test::Dictionary::open("foo.mdx")?;
+/// println!("{:?}", dict.lookup("apple")?);
+/// # Ok::<(), mdict_test::Error>(())
+/// ```
+#[derive(Debug)]
+pub struct Dictionary {
+    mdx: Mdx,
+    max_redirects: usize,
+    lemmatize: bool,
+    // 分别用于 Levenshtein 和 Damerau 距离的模糊查询, 第一次查询时建立
+    bk_trees: [OnceLock<BkTree>; 2],
+    // 词典文件的位置, 索引保存在它旁边, 从内存中打开的词典为 `None`
+    path: Option<PathBuf>,
+    index: OnceLock<Index>,
+    reverse_index: OnceLock<ReverseIndex>,
+    // 打开同名的 mdd 时使用
+    options: ParseOptions,
+    // 词典旁边的 mdd, 第一次导出时打开
+    resources: OnceLock<Vec<Mdd>>,
+}
+
+impl Dictionary {
+    /// 以默认的 [`ParseOptions`] 打开词典
+    pub fn open<P: AsRef<Path>>(path: P) -> Result<Self> {
+        Self::open_with(path, &ParseOptions::default())
+    }
+
+    /// 加密的词典需要在 `options` 中提供注册码, `options.lazy` 为 `true` 时以 mmap 的方式打开
+    pub fn open_with<P: AsRef<Path>>(path: P, options: &ParseOptions) -> Result<Self> {
+        let path = path.as_ref();
+        Ok(Dictionary {
+            mdx: mdx::parse(Source::open(path, options.lazy)?, options)?,
+            max_redirects: MAX_REDIRECTS,
+            lemmatize: true,
+            bk_trees: Default::default(),
+            path: Some(path.to_path_buf()),
+            index: OnceLock::new(),
+            reverse_index: OnceLock::new(),
+            options: options.clone(),
+            resources: OnceLock::new(),
+        })
+    }
+
+    pub fn from_bytes(bytes: &[u8]) -> Result<Self> {
+        Self::from_bytes_with(bytes, &ParseOptions::default())
+    }
+
+    pub fn from_bytes_with(bytes: &[u8], options: &ParseOptions) -> Result<Self> {
+        Ok(Dictionary {
+            mdx: mdx::parse(Source::Bytes(bytes.to_vec()), options)?,
+            max_redirects: MAX_REDIRECTS,
+            lemmatize: true,
+            bk_trees: Default::default(),
+            path: None,
+            index: OnceLock::new(),
+            reverse_index: OnceLock::new(),
+            options: options.clone(),
+            resources: OnceLock::new(),
+        })
+    }
+
+    pub fn metadata(&self) -> &DictMeta {
+        &self.mdx.dict_meta
+    }
+
+    /// `@@@LINK=` 最多跳转的次数, 为 0 时不跳转, 直接返回链接本身
+    pub fn set_max_redirects(&mut self, max_redirects: usize) {
+        self.max_redirects = max_redirects;
+    }
+
+    /// 查不到词时是否按英语的屈折变化尝试原形, 默认开启
+    pub fn set_lemmatize(&mut self, lemmatize: bool) {
+        self.lemmatize = lemmatize;
+    }
+
+    /// 查找 `key` 对应的所有词条, 同形异义词按文件中的顺序返回.
+    /// 按 header 的 KeyCaseSensitive 和 StripKey 匹配, 词条的 `key` 为词典中原本的词头.
+    /// `@@@LINK=` 会跳转到目标词条, 目标不存在, 跳转形成循环或者超过最多跳转的次数时返回链接本身,
+    /// `redirects` 中为已经经过的词头, 其他同形异义词不受影响.
+    /// 没有这个词时查找 `running`, `geese` 这类变化形式可能的原形: 先按不规则变化, 再按规则
+    /// 逐条尝试, 返回第一组找到的原形的词条
+    pub fn lookup(&self, key: &str) -> Result<Vec<Entry>> {
+        let mut entries = Vec::new();
+        for (block, index, key) in self.mdx.find(key)? {
+            self.follow((block, index), key, &mut Vec::new(), &mut entries)?;
+        }
+        if !entries.is_empty() || !self.lemmatize {
+            return Ok(entries.into_iter().map(|v| v.1).collect());
+        }
+
+        for tier in lemma::lemmas(key) {
+            for lemma in tier {
+                let start = entries.len();
+                for (block, index, key) in self.mdx.find(&lemma)? {
+                    self.follow((block, index), key, &mut Vec::new(), &mut entries)?;
+                }
+                for entry in &mut entries[start..] {
+                    entry.1.lemma = Some(lemma.clone());
+                }
+            }
+            if !entries.is_empty() {
+                break;
+            }
+        }
+
+        Ok(entries.into_iter().map(|v| v.1).collect())
+    }
+
+    /// `visited` 为跳转经过的词条, 用于检测循环. 多条链接指向同一个词条时只保留第一个
+    fn follow(
+        &self,
+        position: (usize, usize),
+        key: &str,
+        visited: &mut Vec<((usize, usize), String)>,
+        entries: &mut Vec<((usize, usize), Entry)>,
+    ) -> Result<()> {
+        if entries.iter().any(|v| v.0 == position) {
+            return Ok(());
+        }
+
+        let definition = self.mdx.text(position.0, position.1)?;
+        let entry = |definition| Entry {
+            key: key.to_string(),
+            definition,
+            redirects: visited.iter().map(|v| v.1.clone()).collect(),
+            lemma: None,
+        };
+
+        let target = match link(&definition) {
+            Some(target) if self.max_redirects > 0 => target,
+            _ => {
+                entries.push((position, entry(definition)));
+                return Ok(());
+            }
+        };
+
+        let found = self.mdx.find(target)?;
+        if found.is_empty() || visited.len() >= self.max_redirects {
+            entries.push((position, entry(definition)));
+            return Ok(());
+        }
+
+        // 大小写不敏感的词典中 `Go` 可能链接到 `go`, 查到的词条中包含链接本身,
+        // 词头与目标完全相同的词条优先
+        let mut targets = found
+            .into_iter()
+            .filter(|v| (v.0, v.1) != position && visited.iter().all(|p| p.0 != (v.0, v.1)))
+            .collect::<Vec<_>>();
+        targets.sort_by_key(|v| v.2 != target);
+        if targets.is_empty() {
+            entries.push((position, entry(definition)));
+            return Ok(());
+        }
+
+        visited.push((position, key.to_string()));
+        for (block, index, key) in targets {
+            self.follow((block, index), key, visited, entries)?;
+        }
+        visited.pop();
+
+        Ok(())
+    }
+
+    /// 以 `prefix` 开头的词头, 按词典中的顺序最多返回 `limit` 个, 用于输入时的自动补全.
+    /// 与 [`Dictionary::lookup`] 一样按 KeyCaseSensitive 和 StripKey 匹配
+    pub fn complete(&self, prefix: &str, limit: usize) -> Result<Vec<&str>> {
+        self.mdx.complete(prefix, limit)
+    }
+
+    /// 与 `query` 的编辑距离不超过 `distance` 的词头及其距离, 先按距离再按词典中的顺序排列,
+    /// 最多返回 `limit` 个. 第一次查询时需要读取所有的 key 建立索引
+    pub fn fuzzy(
+        &self,
+        query: &str,
+        distance: Distance,
+        limit: usize,
+    ) -> Result<Vec<(&str, usize)>> {
+        let normalizer = self.mdx.dict_meta.normalizer();
+        let query = normalizer.normalize(query).chars().collect::<Vec<_>>();
+
+        let mut found = self
+            .bk_tree(distance)?
+            .search(&query, distance.max())
+            .into_iter()
+            .flat_map(|(distance, positions)| positions.iter().map(move |v| (distance, *v)))
+            .collect::<Vec<_>>();
+        found.sort_unstable();
+
+        let mut keys: Vec<(&str, usize)> = Vec::new();
+        for (distance, (block, index)) in found {
+            if keys.len() >= limit {
+                break;
+            }
+
+            // 同形异义词只保留一个
+            let key = self.mdx.key(block, index)?;
+            if !keys.iter().any(|v| v.0 == key) {
+                keys.push((key, distance));
+            }
+        }
+
+        Ok(keys)
+    }
+
+    fn bk_tree(&self, distance: Distance) -> Result<&BkTree> {
+        let cache = match distance {
+            Distance::Levenshtein(_) => &self.bk_trees[0],
+            Distance::Damerau(_) => &self.bk_trees[1],
+        };
+        if let Some(tree) = cache.get() {
+            return Ok(tree);
+        }
+
+        let normalizer = self.mdx.dict_meta.normalizer();
+        let mut tree = BkTree::new(distance.metric());
+        for key in self.mdx.keys() {
+            let (block, index, key) = key?;
+            tree.insert(normalizer.normalize(key).chars().collect(), (block, index));
+        }
+
+        Ok(cache.get_or_init(|| tree))
+    }
+
+    /// 在去掉 HTML 标签的释义中检索 `query`, 返回包含所有词的词条, 按相关程度排列, 最多返回 `limit` 个.
+    /// 用引号括起来的 `query` 需要作为短语出现. 第一次检索时建立索引,
+    /// 从文件打开的词典把索引保存在 `.mdx` 旁边的 `.fts` 文件中, 以后直接读取.
+    /// 保存失败时只在内存中使用索引, 错误交给 [`ParseOptions::on_warning`]
+    pub fn full_text(&self, query: &str, limit: usize) -> Result<Vec<Hit<'_>>> {
+        let query = query.trim();
+        let phrase = query.len() >= 2 && query.starts_with('"') && query.ends_with('"');
+
+        let mut terms = fulltext::tokens(query)
+            .into_iter()
+            .map(|v| v.1)
+            .collect::<Vec<_>>();
+        if terms.is_empty() {
+            return Ok(Vec::new());
+        }
+
+        let index = self.index()?;
+        let mut distinct = terms.clone();
+        distinct.sort();
+        distinct.dedup();
+        if !phrase {
+            terms = distinct.clone();
+        }
+
+        let mut hits = Vec::new();
+        for ((block, index), score) in index.search(&distinct) {
+            if hits.len() >= limit {
+                break;
+            }
+
+            let text = fulltext::strip_html(&self.mdx.text(block, index)?);
+            if let Some((snippet, highlights)) = fulltext::snippet(&text, &terms, phrase) {
+                hits.push(Hit {
+                    key: self.mdx.key(block, index)?,
+                    score,
+                    snippet,
+                    highlights,
+                });
+            }
+        }
+
+        Ok(hits)
+    }
+
+    fn index(&self) -> Result<&Index> {
+        if let Some(index) = self.index.get() {
+            return Ok(index);
+        }
+
+        let fingerprint = self.mdx.fingerprint();
+        let path = self.sidecar("fts");
+        if let Some(path) = &path {
+            if let Some(index) = Index::load(path, fingerprint)? {
+                return Ok(self.index.get_or_init(|| index));
+            }
+        }
+
+        // 链接没有需要检索的内容
+        let mut index = Index::new(fingerprint);
+        for key in self.mdx.keys() {
+            let (block, position, _) = key?;
+            let text = self.mdx.text(block, position)?;
+            if link(&text).is_none() {
+                index.add((block, position), &fulltext::strip_html(&text));
+            }
+        }
+
+        // 词典所在的目录可能不可写, 这时只在内存中使用索引
+        if let Some(path) = &path {
+            if let Err(e) = index.save(path) {
+                self.options.warn(Error::Save {
+                    path: path.clone(),
+                    source: Box::new(e),
+                });
+            }
+        }
+
+        Ok(self.index.get_or_init(|| index))
+    }
+
+    /// 以中文查找英文词头: 释义中包含 `query` 的词头及其得分, 按 `query` 在释义中的显著程度排列,
+    /// 最多返回 `limit` 个. 义项的释义排在例句的译文之前, 与 `query` 完全相同的释义排在包含它的
+    /// 更长的释义之前. 与 [`Dictionary::full_text`] 一样, 第一次查询时建立索引, 保存在 `.rev` 文件中
+    pub fn reverse_lookup(&self, query: &str, limit: usize) -> Result<Vec<(&str, f32)>> {
+        let query = query.trim();
+        if !query.chars().any(fulltext::is_cjk) {
+            return Ok(Vec::new());
+        }
+
+        let mut keys: Vec<(&str, f32)> = Vec::new();
+        for ((block, index), score) in self.reverse_index()?.search(query) {
+            if keys.len() >= limit {
+                break;
+            }
+
+            // 同形异义词只保留得分最高的一个
+            let key = self.mdx.key(block, index)?;
+            if !keys.iter().any(|v| v.0 == key) {
+                keys.push((key, score));
+            }
+        }
+
+        Ok(keys)
+    }
+
+    fn reverse_index(&self) -> Result<&ReverseIndex> {
+        if let Some(index) = self.reverse_index.get() {
+            return Ok(index);
+        }
+
+        let fingerprint = self.mdx.fingerprint();
+        let path = self.sidecar("rev");
+        if let Some(path) = &path {
+            if let Some(index) = ReverseIndex::load(path, fingerprint)? {
+                return Ok(self.reverse_index.get_or_init(|| index));
+            }
+        }
+
+        let mut index = ReverseIndex::new(fingerprint);
+        for key in self.mdx.keys() {
+            let (block, position, _) = key?;
+            let text = self.mdx.text(block, position)?;
+            if link(&text).is_none() {
+                index.add((block, position), &text);
+            }
+        }
+
+        if let Some(path) = &path {
+            if let Err(e) = index.save(path) {
+                self.options.warn(Error::Save {
+                    path: path.clone(),
+                    source: Box::new(e),
+                });
+            }
+        }
+
+        Ok(self.reverse_index.get_or_init(|| index))
+    }
+
+    /// 把 `entries` 导出为一个独立的 HTML 文件, 图片, 样式表, 字体和声音等资源从词典旁边的
+    /// `foo.mdd`, `foo.1.mdd` ... 中读取, 以 data URI 的形式内嵌, 样式表中 `url()` 引用的资源同样内嵌.
+    /// 指向导出的词条的 `entry://` 改为页面内的链接. 找不到的资源保持原样, 记录在 [`Export::missing`] 中
+    pub fn export_html(&self, entries: &[Entry]) -> Result<Export> {
+        export::export(entries, self.resources()?)
+    }
+
+    fn resources(&self) -> Result<&[Mdd]> {
+        if let Some(resources) = self.resources.get() {
+            return Ok(resources);
+        }
+
+        let mut resources = Vec::new();
+        if let Some(path) = &self.path {
+            let mut mdd = path.with_extension("mdd");
+            while mdd.exists() {
+                resources.push(Mdd::open(&mdd, &self.options)?);
+                mdd = path.with_extension(format!("{}.mdd", resources.len()));
+            }
+        }
+
+        Ok(self.resources.get_or_init(|| resources))
+    }
+
+    /// 词典旁边的索引文件, 如 `foo.mdx` 的 `foo.mdx.fts`
+    fn sidecar(&self, extension: &str) -> Option<PathBuf> {
+        let mut path = self.path.as_ref()?.as_os_str().to_owned();
+        path.push(".");
+        path.push(extension);
+        Some(path.into())
+    }
+
+    /// 与 `pattern` 匹配的词头, 按词典中的顺序逐个返回, 同形异义词只返回一个.
+    /// 通配符以固定的前缀开头时只解压这个前缀所在的 block, 正则表达式需要遍历所有的词头
+    pub fn search<'a>(
+        &'a self,
+        pattern: Pattern<'_>,
+    ) -> Result<impl Iterator<Item = Result<&'a str>> + 'a> {
+        let normalizer = self.mdx.dict_meta.normalizer();
+        let matcher = pattern.compile(&normalizer)?;
+        let prefix = matcher.prefix.clone();
+
+        let mut last = None;
+        Ok(self
+            .mdx
+            .keys_from(&prefix)
+            .map(|v| v.map(|(_, _, key)| key))
+            .take_while(move |v| match v {
+                Ok(key) => normalizer.normalize(key).starts_with(&prefix),
+                Err(_) => true,
+            })
+            .filter(move |v| match v {
+                Ok(key) if last == Some(*key) || !matcher.is_match(key) => false,
+                Ok(key) => {
+                    last = Some(*key);
+                    true
+                }
+                Err(_) => true,
+            }))
+    }
+
+    /// 分页查找, 跳过前 `offset` 个匹配的词头, 最多返回 `limit` 个
+    pub fn search_page(
+        &self,
+        pattern: Pattern<'_>,
+        offset: usize,
+        limit: usize,
+    ) -> Result<Vec<&str>> {
+        // 跳过的部分中出现的错误同样需要返回
+        let mut keys = self.search(pattern)?;
+        for key in keys.by_ref().take(offset) {
+            key?;
+        }
+
+        keys.take(limit).collect()
+    }
+
+    /// 按文件中的顺序遍历所有的词头
+    pub fn keys(&self) -> impl Iterator<Item = Result<&str>> {
+        self.mdx.keys().map(|v| v.map(|(_, _, key)| key))
+    }
+
+    /// 所有的词头和释义, 释义在迭代时才解码
+    pub fn iter(&self) -> impl Iterator<Item = Result<(&str, String)>> {
+        self.mdx.keys().map(move |v| {
+            let (block, index, key) = v?;
+            Ok((key, self.mdx.text(block, index)?))
+        })
+    }
+}
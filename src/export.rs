@@ -0,0 +1,258 @@
+use std::collections::HashMap;
+
+use base64::{engine::general_purpose::STANDARD, Engine};
+
+use crate::dictionary::Entry;
+use crate::links::{self, encode, entry, escape, is_absolute, is_resource, Rewriter};
+use crate::mdict::{mdd::Mdd, Error, Result};
+
+/// 扩展名对应的 MIME 类型
+const MIME: &[(&str, &str)] = &[
+    ("css", "text/css"),
+    ("js", "text/javascript"),
+    ("png", "image/png"),
+    ("jpg", "image/jpeg"),
+    ("jpeg", "image/jpeg"),
+    ("gif", "image/gif"),
+    ("svg", "image/svg+xml"),
+    ("webp", "image/webp"),
+    ("bmp", "image/bmp"),
+    ("ico", "image/x-icon"),
+    ("ttf", "font/ttf"),
+    ("otf", "font/otf"),
+    ("woff", "font/woff"),
+    ("woff2", "font/woff2"),
+    ("eot", "application/vnd.ms-fontobject"),
+    ("mp3", "audio/mpeg"),
+    ("wav", "audio/wav"),
+    ("ogg", "audio/ogg"),
+    ("spx", "audio/ogg"),
+    ("m4a", "audio/mp4"),
+    ("mp4", "video/mp4"),
+];
+
+/// 导出为 HTML 的结果
+#[derive(Debug, Clone, PartialEq)]
+pub struct Export {
+    /// 完整的 HTML 文档, 引用的资源都以 data URI 的形式内嵌
+    pub html: String,
+    /// 在 mdd 中找不到的资源, 按第一次引用的顺序排列
+    pub missing: Vec<String>,
+}
+
+/// 把引用的资源替换为 data URI
+struct Inliner<'a> {
+    resources: &'a [Mdd],
+    // 导出的词条, 指向它们的 `entry://` 改为页面内的链接
+    keys: Vec<&'a str>,
+    // 资源的路径和它的 data URI, 找不到的为 `None`
+    cache: HashMap<String, Option<String>>,
+    missing: Vec<String>,
+    // 读取资源时遇到的第一个错误
+    error: Option<Error>,
+}
+
+impl Rewriter for Inliner<'_> {
+    fn attribute(&mut self, name: &str, attribute: &str, value: &str) -> Option<String> {
+        if attribute == "style" {
+            return Some(self.css(value, "")).filter(|v| v != value);
+        } else if attribute != "href" && attribute != "src" {
+            return None;
+        }
+
+        if let Some((word, fragment)) = entry(value) {
+            if word.is_empty() {
+                Some(fragment.to_string())
+            } else if self.keys.contains(&word) {
+                Some(format!("#{}", encode(word, false)))
+            } else {
+                None
+            }
+        } else if value.to_ascii_lowercase().starts_with("sound://") {
+            self.data_uri(&value[8..], "")
+        } else if is_resource(name, attribute, value) {
+            self.data_uri(value, "")
+        } else {
+            None
+        }
+    }
+
+    fn style(&mut self, css: &str) -> Option<String> {
+        Some(self.css(css, ""))
+    }
+}
+
+impl Inliner<'_> {
+    /// `url` 指向的资源的 data URI, `base` 为引用它的样式表所在的目录
+    fn data_uri(&mut self, url: &str, base: &str) -> Option<String> {
+        let path = resolve(base, url);
+        if let Some(uri) = self.cache.get(&path) {
+            return uri.clone();
+        }
+        // 样式表互相引用时不会无限递归
+        self.cache.insert(path.clone(), None);
+
+        let data = match self.find(&path) {
+            Ok(Some(v)) => v,
+            Ok(None) => {
+                self.missing.push(path);
+                return None;
+            }
+            Err(e) => {
+                self.error.get_or_insert(e);
+                return None;
+            }
+        };
+
+        // 样式表中引用的资源相对于样式表所在的目录
+        let mime = mime(&path);
+        let data = if mime == "text/css" {
+            let dir = path.rfind('/').map_or("", |v| &path[..v + 1]);
+            self.css(&String::from_utf8_lossy(&data), dir).into_bytes()
+        } else {
+            data
+        };
+
+        let uri = format!("data:{};base64,{}", mime, STANDARD.encode(data));
+        self.cache.insert(path, Some(uri.clone()));
+        Some(uri)
+    }
+
+    fn find(&self, path: &str) -> Result<Option<Vec<u8>>> {
+        for mdd in self.resources {
+            if let Some(data) = mdd.resource(path)? {
+                return Ok(Some(data));
+            }
+        }
+        Ok(None)
+    }
+
+    /// 替换样式中 `url()` 引用的资源, 找不到的保持不变
+    fn css(&mut self, css: &str, base: &str) -> String {
+        let lower = css.to_ascii_lowercase();
+        let mut text = String::with_capacity(css.len());
+        let mut last = 0;
+
+        while let Some(start) = lower[last..].find("url(").map(|v| last + v) {
+            let end = match css[start..].find(')') {
+                Some(v) => start + v + 1,
+                None => break,
+            };
+            let url = css[start + 4..end - 1]
+                .trim()
+                .trim_matches(|c| c == '"' || c == '\'');
+
+            text.push_str(&css[last..start]);
+            match Some(url)
+                .filter(|v| !v.is_empty() && !is_absolute(v))
+                .and_then(|v| self.data_uri(v, base))
+            {
+                Some(uri) => text.push_str(&format!("url(\"{}\")", uri)),
+                None => text.push_str(&css[start..end]),
+            }
+            last = end;
+        }
+        text.push_str(&css[last..]);
+
+        text
+    }
+}
+
+/// 资源在 mdd 中的路径, 以 `/` 分隔, 去掉查询参数并解码百分号编码
+fn resolve(base: &str, url: &str) -> String {
+    let url = url.split(['?', '#']).next().unwrap_or_default();
+    let url = decode(url);
+
+    let mut segments = if url.starts_with(['/', '\\']) {
+        Vec::new()
+    } else {
+        base.split('/').filter(|v| !v.is_empty()).collect()
+    };
+    let path = links::path(&url);
+    for segment in path.split('/') {
+        match segment {
+            "" | "." => {}
+            ".." => {
+                segments.pop();
+            }
+            v => segments.push(v),
+        }
+    }
+
+    segments.join("/")
+}
+
+fn decode(url: &str) -> String {
+    let bytes = url.as_bytes();
+    let mut decoded = Vec::with_capacity(bytes.len());
+    let mut i = 0;
+    while i < bytes.len() {
+        let hex = bytes
+            .get(i + 1..i + 3)
+            .filter(|_| bytes[i] == b'%')
+            .and_then(|v| u8::from_str_radix(std::str::from_utf8(v).ok()?, 16).ok());
+        match hex {
+            Some(c) => {
+                decoded.push(c);
+                i += 3;
+            }
+            None => {
+                decoded.push(bytes[i]);
+                i += 1;
+            }
+        }
+    }
+    String::from_utf8_lossy(&decoded).into_owned()
+}
+
+fn mime(path: &str) -> &'static str {
+    let extension = path
+        .rsplit('.')
+        .next()
+        .unwrap_or_default()
+        .to_ascii_lowercase();
+    MIME.iter()
+        .find(|v| v.0 == extension)
+        .map_or("application/octet-stream", |v| v.1)
+}
+
+/// 把 `entries` 导出为一个 HTML 文档, 每个词条为一个 `<article>`
+pub(crate) fn export(entries: &[Entry], resources: &[Mdd]) -> Result<Export> {
+    let mut inliner = Inliner {
+        resources,
+        keys: entries.iter().map(|v| v.key.as_str()).collect(),
+        cache: HashMap::new(),
+        missing: Vec::new(),
+        error: None,
+    };
+
+    let mut title = inliner.keys.clone();
+    title.dedup();
+    let title = title.join(", ");
+    let mut html = format!(
+        "<!DOCTYPE html>\n<html>\n<head>\n<meta charset=\"utf-8\">\n<title>{}</title>\n</head>\n<body>\n",
+        escape(&title).replace('<', "&lt;")
+    );
+    for (i, entry) in entries.iter().enumerate() {
+        // 同形异义词只有第一个词条带有 id
+        if entries[..i].iter().any(|v| v.key == entry.key) {
+            html.push_str("<article>\n");
+        } else {
+            html.push_str(&format!(
+                "<article id=\"{}\">\n",
+                escape(&encode(&entry.key, false))
+            ));
+        }
+        html.push_str(&links::rewrite(&entry.definition, &mut inliner));
+        html.push_str("\n</article>\n");
+    }
+    html.push_str("</body>\n</html>\n");
+
+    match inliner.error {
+        Some(e) => Err(e),
+        None => Ok(Export {
+            html,
+            missing: inliner.missing,
+        }),
+    }
+}
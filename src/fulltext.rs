@@ -0,0 +1,386 @@
+use std::{
+    collections::{BTreeMap, HashMap},
+    fs,
+    io::{self, Read, Write},
+    ops::Range,
+    path::Path,
+};
+
+use byteorder::{LittleEndian, WriteBytesExt};
+use flate2::{read::ZlibDecoder, write::ZlibEncoder, Compression};
+use nom::{
+    bytes::complete::tag,
+    combinator::map_res,
+    multi::{length_count, length_data},
+    number::complete::{le_u16, le_u32, le_u64},
+    sequence::tuple,
+    IResult,
+};
+
+use crate::html::{self, Token};
+use crate::mdict::Result;
+
+/// 索引文件的开头, 最后一个字节为格式的版本
+const MAGIC: &[u8] = b"MDFT\x01";
+
+/// 摘要中匹配位置之前保留的字符数
+const CONTEXT: usize = 40;
+/// 摘要最多包含的字符数
+const SNIPPET: usize = 160;
+
+// BM25 的参数
+const K1: f32 = 1.2;
+const B: f32 = 0.75;
+
+/// 全文检索的一个结果
+#[derive(Debug, Clone, PartialEq)]
+pub struct Hit<'a> {
+    pub key: &'a str,
+    pub score: f32,
+    /// 去掉 HTML 标签之后, 第一个匹配位置附近的一段释义
+    pub snippet: String,
+    /// 匹配的词在 `snippet` 中的位置
+    pub highlights: Vec<Range<usize>>,
+}
+
+/// 以词条为文档的倒排索引
+#[derive(Debug)]
+pub(crate) struct Index {
+    fingerprint: u64,
+    // 词条所在的 block, 位置和词数
+    docs: Vec<(u32, u32, u32)>,
+    // 词和包含这个词的文档及词频, 文档按顺序排列
+    terms: BTreeMap<String, Vec<(u32, u32)>>,
+}
+
+impl Index {
+    pub(crate) fn new(fingerprint: u64) -> Self {
+        Index {
+            fingerprint,
+            docs: Vec::new(),
+            terms: BTreeMap::new(),
+        }
+    }
+
+    /// `text` 为去掉 HTML 标签之后的释义
+    pub(crate) fn add(&mut self, position: (usize, usize), text: &str) {
+        let doc = self.docs.len() as u32;
+        let tokens = tokens(text);
+        self.docs
+            .push((position.0 as u32, position.1 as u32, tokens.len() as u32));
+
+        let mut counts: HashMap<String, u32> = HashMap::new();
+        let mut unigrams: HashMap<String, u32> = HashMap::new();
+        for (_, token) in tokens {
+            *counts.entry(token).or_default() += 1;
+        }
+        // 中日韩文字的单字也加入索引, 用于只有一个字的查询
+        for c in text.chars().filter(|c| is_cjk(*c)) {
+            *unigrams.entry(c.to_string()).or_default() += 1;
+        }
+        counts.extend(unigrams);
+        for (token, count) in counts {
+            self.terms.entry(token).or_default().push((doc, count));
+        }
+    }
+
+    /// 包含所有 `terms` 的词条所在的 block 和位置, 按 BM25 的得分从高到低排列
+    pub(crate) fn search(&self, terms: &[String]) -> Vec<((usize, usize), f32)> {
+        let mut postings = Vec::new();
+        for term in terms {
+            match self.terms.get(term) {
+                Some(v) => postings.push(v),
+                None => return Vec::new(),
+            }
+        }
+
+        let n = self.docs.len() as f32;
+        let average = self.docs.iter().map(|v| v.2 as f32).sum::<f32>() / n.max(1.0);
+
+        let mut scores: HashMap<u32, (usize, f32)> = HashMap::new();
+        for posting in &postings {
+            let df = posting.len() as f32;
+            let idf = (1.0 + (n - df + 0.5) / (df + 0.5)).ln();
+            for &(doc, tf) in posting.iter() {
+                let len = self.docs[doc as usize].2 as f32;
+                let tf = tf as f32;
+                let score = scores.entry(doc).or_default();
+                score.0 += 1;
+                score.1 += idf * tf * (K1 + 1.0) / (tf + K1 * (1.0 - B + B * len / average));
+            }
+        }
+
+        let mut found = scores
+            .into_iter()
+            .filter(|v| (v.1).0 == postings.len())
+            .map(|(doc, (_, score))| (doc, score))
+            .collect::<Vec<_>>();
+        found.sort_by(|a, b| b.1.total_cmp(&a.1).then(a.0.cmp(&b.0)));
+
+        found
+            .into_iter()
+            .map(|(doc, score)| {
+                let (block, index, _) = self.docs[doc as usize];
+                ((block as usize, index as usize), score)
+            })
+            .collect()
+    }
+
+    pub(crate) fn save(&self, path: &Path) -> Result<()> {
+        let mut body = Vec::new();
+        body.write_u32::<LittleEndian>(self.docs.len() as u32)?;
+        for &(block, index, len) in &self.docs {
+            body.write_u32::<LittleEndian>(block)?;
+            body.write_u32::<LittleEndian>(index)?;
+            body.write_u32::<LittleEndian>(len)?;
+        }
+        body.write_u32::<LittleEndian>(self.terms.len() as u32)?;
+        for (term, posting) in &self.terms {
+            body.write_u16::<LittleEndian>(term.len() as u16)?;
+            body.write_all(term.as_bytes())?;
+            body.write_u32::<LittleEndian>(posting.len() as u32)?;
+            for &(doc, tf) in posting {
+                body.write_u32::<LittleEndian>(doc)?;
+                body.write_u32::<LittleEndian>(tf)?;
+            }
+        }
+
+        save_file(path, MAGIC, self.fingerprint, &body)
+    }
+
+    pub(crate) fn load(path: &Path, fingerprint: u64) -> Result<Option<Self>> {
+        let body = match load_file(path, MAGIC, fingerprint)? {
+            Some(v) => v,
+            None => return Ok(None),
+        };
+
+        Ok(match index(&body) {
+            Ok((&[], (docs, terms))) => Some(Index {
+                fingerprint,
+                docs,
+                terms: terms.into_iter().collect(),
+            }),
+            _ => None,
+        })
+    }
+}
+
+/// 保存在词典旁边的索引文件, 依次为 `magic`, 词典的指纹和 zlib 压缩的内容.
+/// 先写入临时文件再改名, 中途失败不会留下不完整的文件
+pub(crate) fn save_file(path: &Path, magic: &[u8], fingerprint: u64, body: &[u8]) -> Result<()> {
+    let mut encoder = ZlibEncoder::new(Vec::new(), Compression::default());
+    encoder.write_all(body)?;
+
+    let mut data = magic.to_vec();
+    data.write_u64::<LittleEndian>(fingerprint)?;
+    data.extend(encoder.finish()?);
+
+    // 在索引文件的名字后面加上 `.tmp`, 不同的索引同时保存时不会写到同一个临时文件
+    let mut tmp = path.as_os_str().to_owned();
+    tmp.push(".tmp");
+    fs::write(&tmp, data)?;
+    fs::rename(&tmp, path)?;
+    Ok(())
+}
+
+/// 文件不存在, 格式不对或者不属于这个词典时返回 `None`, 需要重新建立索引
+pub(crate) fn load_file(path: &Path, magic: &[u8], fingerprint: u64) -> Result<Option<Vec<u8>>> {
+    let data = match fs::read(path) {
+        Ok(v) => v,
+        Err(e) if e.kind() == io::ErrorKind::NotFound => return Ok(None),
+        Err(e) => return Err(e.into()),
+    };
+
+    let body = match header(&data, magic) {
+        Ok((body, v)) if v == fingerprint => body,
+        _ => return Ok(None),
+    };
+
+    let mut buf = Vec::new();
+    if ZlibDecoder::new(body).read_to_end(&mut buf).is_err() {
+        return Ok(None);
+    }
+
+    Ok(Some(buf))
+}
+
+fn header<'a>(in_: &'a [u8], magic: &[u8]) -> IResult<&'a [u8], u64, ()> {
+    let (in_, _) = tag(magic)(in_)?;
+    le_u64(in_)
+}
+
+type Docs = Vec<(u32, u32, u32)>;
+type Terms = Vec<(String, Vec<(u32, u32)>)>;
+
+fn index(in_: &[u8]) -> IResult<&[u8], (Docs, Terms), ()> {
+    tuple((
+        length_count(le_u32, tuple((le_u32, le_u32, le_u32))),
+        length_count(
+            le_u32,
+            tuple((
+                map_res(length_data(le_u16), |v: &[u8]| {
+                    String::from_utf8(v.to_vec())
+                }),
+                length_count(le_u32, tuple((le_u32, le_u32))),
+            )),
+        ),
+    ))(in_)
+}
+
+/// 中日韩文字, 这些文字之间没有空格, 以相邻的两个字作为一个词
+pub(crate) fn is_cjk(c: char) -> bool {
+    matches!(c,
+        '\u{3040}'..='\u{30ff}'
+        | '\u{3400}'..='\u{4dbf}'
+        | '\u{4e00}'..='\u{9fff}'
+        | '\u{ac00}'..='\u{d7af}'
+        | '\u{f900}'..='\u{faff}'
+        | '\u{20000}'..='\u{2a6df}')
+}
+
+/// 把文本切分为词和词在文本中的位置. 其他文字以连续的字母和数字为一个词, 转为小写;
+/// 中日韩文字取相邻的两个字, 只有一个字时取这个字
+pub(crate) fn tokens(text: &str) -> Vec<(Range<usize>, String)> {
+    let mut tokens = Vec::new();
+    let mut word: Option<usize> = None;
+    let mut run: Vec<(usize, char)> = Vec::new();
+
+    for (i, c) in text.char_indices().chain([(text.len(), ' ')]) {
+        let cjk = is_cjk(c);
+        if !cjk && !run.is_empty() {
+            if run.len() == 1 {
+                let (j, c) = run[0];
+                tokens.push((j..j + c.len_utf8(), c.to_string()));
+            }
+            for pair in run.windows(2) {
+                let end = pair[1].0 + pair[1].1.len_utf8();
+                tokens.push((pair[0].0..end, text[pair[0].0..end].to_string()));
+            }
+            run.clear();
+        }
+
+        if cjk || !c.is_alphanumeric() {
+            if let Some(start) = word.take() {
+                tokens.push((start..i, text[start..i].to_lowercase()));
+            }
+        }
+
+        if cjk {
+            run.push((i, c));
+        } else if c.is_alphanumeric() {
+            word.get_or_insert(i);
+        }
+    }
+
+    tokens
+}
+
+/// 不会产生空白的标签
+const INLINE: &[&str] = &[
+    "a", "b", "i", "u", "s", "em", "strong", "span", "font", "sup", "sub", "small", "big",
+];
+
+/// 去掉 HTML 标签以及 `<script>` 和 `<style>` 的内容, 解码字符实体, 连续的空白合并为一个空格
+pub(crate) fn strip_html(html: &str) -> String {
+    let mut text = String::with_capacity(html.len());
+
+    for token in html::tokens(html) {
+        match token {
+            Token::Text(v) => {
+                for c in html::decode(v).chars() {
+                    if c.is_whitespace() {
+                        space(&mut text);
+                    } else {
+                        text.push(c);
+                    }
+                }
+            }
+            Token::Tag(tag) if INLINE.contains(&tag.name.as_str()) => {}
+            Token::Tag(_) => space(&mut text),
+            Token::Comment(_) | Token::Raw(_) => {}
+        }
+    }
+
+    text.trim_end().to_string()
+}
+
+fn space(text: &mut String) {
+    if !text.is_empty() && !text.ends_with(' ') {
+        text.push(' ');
+    }
+}
+
+/// 第一个匹配位置附近的一段文本, 以及其中匹配的词的位置.
+/// `phrase` 为 `true` 时 `terms` 需要按顺序相邻出现, 没有出现时返回 `None`
+pub(crate) fn snippet(
+    text: &str,
+    terms: &[String],
+    phrase: bool,
+) -> Option<(String, Vec<Range<usize>>)> {
+    let tokens = tokens(text);
+    let matched = if phrase {
+        tokens
+            .windows(terms.len().max(1))
+            .filter(|w| w.iter().zip(terms).all(|(t, q)| t.1 == *q))
+            .map(|w| w[0].0.start..w[w.len() - 1].0.end)
+            .collect::<Vec<_>>()
+    } else {
+        let mut matched = tokens
+            .iter()
+            .filter(|t| terms.contains(&t.1))
+            .map(|t| t.0.clone())
+            .collect::<Vec<_>>();
+        // 单字只在索引中出现, 需要在文本中查找
+        for term in terms
+            .iter()
+            .filter(|v| v.chars().count() == 1 && v.chars().all(is_cjk))
+        {
+            matched.extend(
+                text.match_indices(term.as_str())
+                    .map(|v| v.0..v.0 + v.1.len()),
+            );
+        }
+        matched.sort_by_key(|v| (v.start, v.end));
+        matched
+    };
+    let first = matched.first()?;
+
+    let mut start = text[..first.start]
+        .char_indices()
+        .rev()
+        .nth(CONTEXT - 1)
+        .map_or(0, |v| v.0);
+    // 不从单词的中间开始
+    if start > 0 && !is_cjk(text[start..].chars().next().unwrap_or(' ')) {
+        if let Some(v) = text[start..first.start].find(' ') {
+            start += v + 1;
+        }
+    }
+    let end = text[start..]
+        .char_indices()
+        .nth(SNIPPET)
+        .map_or(text.len(), |v| start + v.0)
+        .max(first.end);
+
+    let mut snippet = String::new();
+    if start > 0 {
+        snippet.push('…');
+    }
+    let shift = snippet.len();
+    snippet.push_str(&text[start..end]);
+    if end < text.len() {
+        snippet.push('…');
+    }
+
+    // 中日韩文字的词互相重叠, 需要合并
+    let mut highlights: Vec<Range<usize>> = Vec::new();
+    for range in matched.into_iter().filter(|v| v.end <= end) {
+        let range = range.start - start + shift..range.end - start + shift;
+        match highlights.last_mut() {
+            Some(last) if range.start <= last.end => last.end = last.end.max(range.end),
+            _ => highlights.push(range),
+        }
+    }
+
+    Some((snippet, highlights))
+}
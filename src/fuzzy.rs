@@ -0,0 +1,172 @@
+/// 模糊查询使用的编辑距离, 参数为允许的最大距离
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub enum Distance {
+    Levenshtein(usize),
+    /// 相邻两个字符交换位置也算作一次编辑
+    Damerau(usize),
+}
+
+impl Distance {
+    pub(crate) fn max(&self) -> usize {
+        match *self {
+            Distance::Levenshtein(v) | Distance::Damerau(v) => v,
+        }
+    }
+
+    pub(crate) fn metric(&self) -> fn(&[char], &[char]) -> usize {
+        match self {
+            Distance::Levenshtein(_) => levenshtein,
+            Distance::Damerau(_) => damerau,
+        }
+    }
+}
+
+fn levenshtein(a: &[char], b: &[char]) -> usize {
+    let mut prev = (0..=b.len()).collect::<Vec<_>>();
+    let mut cur = vec![0; b.len() + 1];
+
+    for (i, ca) in a.iter().enumerate() {
+        cur[0] = i + 1;
+        for (j, cb) in b.iter().enumerate() {
+            let cost = if ca == cb { 0 } else { 1 };
+            cur[j + 1] = (prev[j] + cost).min(prev[j + 1] + 1).min(cur[j] + 1);
+        }
+        std::mem::swap(&mut prev, &mut cur);
+    }
+
+    prev[b.len()]
+}
+
+/// 不受限制的 Damerau-Levenshtein 距离, 满足三角不等式, 可以用于 BK-tree
+fn damerau(a: &[char], b: &[char]) -> usize {
+    let (n, m) = (a.len(), b.len());
+    let inf = n + m;
+    let width = m + 2;
+
+    // (n + 2) * (m + 2) 的矩阵, 第一行和第一列为哨兵
+    let mut d = vec![0; (n + 2) * width];
+    d[0] = inf;
+    for i in 0..=n {
+        d[(i + 1) * width] = inf;
+        d[(i + 1) * width + 1] = i;
+    }
+    for j in 0..=m {
+        d[j + 1] = inf;
+        d[width + j + 1] = j;
+    }
+
+    // 字符在 a 中最后出现的位置, key 都很短, 不必用 HashMap
+    let mut last: Vec<(char, usize)> = Vec::with_capacity(n);
+
+    for i in 1..=n {
+        let mut db = 0;
+        for j in 1..=m {
+            let i1 = last.iter().find(|v| v.0 == b[j - 1]).map_or(0, |v| v.1);
+            let j1 = db;
+            let cost = if a[i - 1] == b[j - 1] {
+                db = j;
+                0
+            } else {
+                1
+            };
+
+            d[(i + 1) * width + j + 1] = (d[i * width + j] + cost)
+                .min(d[(i + 1) * width + j] + 1)
+                .min(d[i * width + j + 1] + 1)
+                .min(d[i1 * width + j1] + (i - i1 - 1) + 1 + (j - j1 - 1));
+        }
+
+        match last.iter_mut().find(|v| v.0 == a[i - 1]) {
+            Some(v) => v.1 = i,
+            None => last.push((a[i - 1], i)),
+        }
+    }
+
+    d[(n + 1) * width + m + 1]
+}
+
+#[derive(Debug)]
+struct Node {
+    key: Vec<char>,
+    // 规范化之后相同的 key 所在的 block 和位置
+    positions: Vec<(usize, usize)>,
+    // 与子节点的距离和子节点
+    children: Vec<(usize, usize)>,
+}
+
+/// 以编辑距离建立的 BK-tree, 查询时只需要计算一小部分 key 的距离
+#[derive(Debug)]
+pub(crate) struct BkTree {
+    nodes: Vec<Node>,
+    metric: fn(&[char], &[char]) -> usize,
+}
+
+impl BkTree {
+    pub(crate) fn new(metric: fn(&[char], &[char]) -> usize) -> Self {
+        BkTree {
+            nodes: Vec::new(),
+            metric,
+        }
+    }
+
+    pub(crate) fn insert(&mut self, key: Vec<char>, position: (usize, usize)) {
+        if self.nodes.is_empty() {
+            self.nodes.push(Node {
+                key,
+                positions: vec![position],
+                children: Vec::new(),
+            });
+            return;
+        }
+
+        let mut node = 0;
+        loop {
+            let distance = (self.metric)(&key, &self.nodes[node].key);
+            if distance == 0 {
+                self.nodes[node].positions.push(position);
+                return;
+            }
+
+            match self.nodes[node].children.iter().find(|v| v.0 == distance) {
+                Some(child) => node = child.1,
+                None => {
+                    let child = self.nodes.len();
+                    self.nodes[node].children.push((distance, child));
+                    self.nodes.push(Node {
+                        key,
+                        positions: vec![position],
+                        children: Vec::new(),
+                    });
+                    return;
+                }
+            }
+        }
+    }
+
+    /// 距离不超过 `max` 的 key 的距离和位置
+    pub(crate) fn search(&self, query: &[char], max: usize) -> Vec<(usize, &[(usize, usize)])> {
+        let mut found = Vec::new();
+        let mut stack = if self.nodes.is_empty() {
+            vec![]
+        } else {
+            vec![0]
+        };
+
+        while let Some(node) = stack.pop() {
+            let node = &self.nodes[node];
+            let distance = (self.metric)(query, &node.key);
+            if distance <= max {
+                found.push((distance, node.positions.as_slice()));
+            }
+
+            stack.extend(
+                node.children
+                    .iter()
+                    .filter(|v| v.0 + max >= distance && v.0 <= distance + max)
+                    .map(|v| v.1),
+            );
+        }
+
+        found
+    }
+}
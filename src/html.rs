@@ -0,0 +1,282 @@
+use std::ops::Range;
+
+/// 没有结束标签的元素
+pub(crate) const VOID: &[&str] = &["br", "hr", "img", "input", "link", "meta", "source", "wbr"];
+
+/// HTML 中的一段, 依次连接起来就是原本的 HTML
+#[derive(Debug, Clone, PartialEq)]
+pub(crate) enum Token<'a> {
+    /// 标签之间的文本, 没有解码字符实体
+    Text(&'a str),
+    Tag(Tag<'a>),
+    /// `<!-- -->` 注释
+    Comment(&'a str),
+    /// `<script>` 和 `<style>` 的内容
+    Raw(&'a str),
+}
+
+/// 开始或者结束标签
+#[derive(Debug, Clone, PartialEq)]
+pub(crate) struct Tag<'a> {
+    /// 小写的标签名, `<!DOCTYPE>` 这样的标签为空
+    pub name: String,
+    /// 整个标签, 包括 `<` 和 `>`
+    pub text: &'a str,
+    pub closing: bool,
+}
+
+impl<'a> Tag<'a> {
+    fn new(text: &'a str) -> Self {
+        let name = text[1..]
+            .trim_start_matches('/')
+            .split(|c: char| !c.is_ascii_alphanumeric())
+            .next()
+            .unwrap_or_default()
+            .to_ascii_lowercase();
+        Tag {
+            name,
+            text,
+            closing: text[1..].starts_with('/'),
+        }
+    }
+
+    /// 开始标签之后还有内容和结束标签, 不包括 `<br>` 和 `<br/>` 这样的标签
+    pub(crate) fn is_open(&self) -> bool {
+        !self.closing
+            && !self.name.is_empty()
+            && !VOID.contains(&self.name.as_str())
+            && !self.text.trim_end_matches('>').ends_with('/')
+    }
+
+    /// 属性 `name` 的值, 没有解码字符实体
+    pub(crate) fn attribute(&self, name: &str) -> Option<&'a str> {
+        attributes(self.text)
+            .into_iter()
+            .find(|v| v.0 == name)
+            .map(|(_, range)| &self.text[range])
+    }
+}
+
+/// 依次返回 HTML 中的文本, 标签和注释
+pub(crate) struct Tokens<'a> {
+    rest: &'a str,
+    // 上一个标签为 `<script>` 或 `<style>` 时, 它的结束标签
+    raw: Option<String>,
+}
+
+impl<'a> Iterator for Tokens<'a> {
+    type Item = Token<'a>;
+
+    fn next(&mut self) -> Option<Token<'a>> {
+        if let Some(close) = self.raw.take() {
+            let end = self
+                .rest
+                .to_ascii_lowercase()
+                .find(&close)
+                .unwrap_or(self.rest.len());
+            let (raw, rest) = self.rest.split_at(end);
+            self.rest = rest;
+            if !raw.is_empty() {
+                return Some(Token::Raw(raw));
+            }
+        }
+        if self.rest.is_empty() {
+            return None;
+        }
+
+        let rest = self.rest;
+        let (token, end) = if !rest.starts_with('<') {
+            let end = rest.find('<').unwrap_or(rest.len());
+            (Token::Text(&rest[..end]), end)
+        } else if rest.starts_with("<!--") {
+            let end = rest.find("-->").map_or(rest.len(), |v| v + 3);
+            (Token::Comment(&rest[..end]), end)
+        } else {
+            let end = rest.find('>').map_or(rest.len(), |v| v + 1);
+            let tag = Tag::new(&rest[..end]);
+            if !tag.closing && (tag.name == "script" || tag.name == "style") {
+                self.raw = Some(format!("</{}", tag.name));
+            }
+            (Token::Tag(tag), end)
+        };
+        self.rest = &rest[end..];
+
+        Some(token)
+    }
+}
+
+pub(crate) fn tokens(html: &str) -> Tokens<'_> {
+    Tokens {
+        rest: html,
+        raw: None,
+    }
+}
+
+/// 标签中每个属性的名字 (小写) 和值的位置, 位置不包括引号. 没有值的属性不返回
+pub(crate) fn attributes(tag: &str) -> Vec<(String, Range<usize>)> {
+    let bytes = tag.as_bytes();
+    let len = tag.trim_end_matches('>').len();
+    let separator = |c: u8| c.is_ascii_whitespace() || c == b'/';
+
+    // 跳过 `<` 和标签名
+    let mut i = 1;
+    while i < len && !separator(bytes[i]) {
+        i += 1;
+    }
+
+    let mut attributes = Vec::new();
+    loop {
+        while i < len && separator(bytes[i]) {
+            i += 1;
+        }
+        if i >= len {
+            break;
+        }
+
+        let start = i;
+        while i < len && !separator(bytes[i]) && bytes[i] != b'=' {
+            i += 1;
+        }
+        let name = tag[start..i].to_ascii_lowercase();
+        while i < len && bytes[i].is_ascii_whitespace() {
+            i += 1;
+        }
+        if i >= len || bytes[i] != b'=' {
+            continue;
+        }
+        i += 1;
+        while i < len && bytes[i].is_ascii_whitespace() {
+            i += 1;
+        }
+        if i >= len {
+            break;
+        }
+
+        let value = match bytes[i] {
+            q @ (b'"' | b'\'') => {
+                let end = tag[i + 1..len].find(q as char).map_or(len, |v| i + 1 + v);
+                let value = i + 1..end;
+                i = (end + 1).min(len);
+                value
+            }
+            _ => {
+                let start = i;
+                while i < len && !bytes[i].is_ascii_whitespace() {
+                    i += 1;
+                }
+                start..i
+            }
+        };
+        attributes.push((name, value));
+    }
+
+    attributes
+}
+
+/// 解码文本和属性值中的字符实体, 不认识的保持不变
+pub(crate) fn decode(text: &str) -> String {
+    let mut decoded = String::with_capacity(text.len());
+    let mut rest = text;
+
+    while let Some(start) = rest.find('&') {
+        decoded.push_str(&rest[..start]);
+        rest = &rest[start..];
+
+        let entity = rest[1..]
+            .find(';')
+            .filter(|v| *v <= 8)
+            .and_then(|v| Some((entity(&rest[1..v + 1])?, v + 2)));
+        match entity {
+            Some((c, len)) => {
+                decoded.push(c);
+                rest = &rest[len..];
+            }
+            None => {
+                decoded.push('&');
+                rest = &rest[1..];
+            }
+        }
+    }
+    decoded.push_str(rest);
+
+    decoded
+}
+
+/// 解码 `&name;` 中的 `name`
+fn entity(name: &str) -> Option<char> {
+    match name {
+        "amp" => Some('&'),
+        "lt" => Some('<'),
+        "gt" => Some('>'),
+        "quot" => Some('"'),
+        "apos" => Some('\''),
+        "nbsp" => Some(' '),
+        _ => {
+            let code = match name.strip_prefix("#x").or_else(|| name.strip_prefix("#X")) {
+                Some(hex) => u32::from_str_radix(hex, 16).ok()?,
+                None => name.strip_prefix('#')?.parse().ok()?,
+            };
+            char::from_u32(code)
+        }
+    }
+}
+
+/// 各个词典的写法不同, 只能按常见的 class 猜测元素是不是例句
+pub(crate) fn is_example(class: &str) -> bool {
+    class.to_ascii_lowercase().split_whitespace().any(|v| {
+        matches!(v, "x" | "eg" | "ex" | "exa")
+            || ["exam", "sent", "quote", "collo"]
+                .iter()
+                .any(|h| v.contains(h))
+    })
+}
+
+/// 元素是不是词头
+pub(crate) fn is_headword(class: &str) -> bool {
+    class
+        .to_ascii_lowercase()
+        .split_whitespace()
+        .any(|v| matches!(v, "h" | "hw" | "hwd" | "headword" | "head"))
+}
+
+#[cfg(test)]
+mod tests {
+    use super::{decode, tokens, Token};
+
+    #[test]
+    fn tokens_cover_input() {
+        let html = r#"a<b class="x">b</b><!-- <i> --><script>if (a<b) {}</script><br/>&amp;"#;
+        assert_eq!(
+            tokens(html)
+                .map(|v| match v {
+                    Token::Tag(tag) => tag.text,
+                    Token::Text(v) | Token::Comment(v) | Token::Raw(v) => v,
+                })
+                .collect::<String>(),
+            html
+        );
+        assert!(tokens(html).any(|v| v == Token::Raw("if (a<b) {}")));
+    }
+
+    #[test]
+    fn tags() {
+        let tags = tokens(r#"<SPAN Class='hw x'></span><br/><img src=a.png>"#)
+            .filter_map(|v| match v {
+                Token::Tag(tag) => Some(tag),
+                _ => None,
+            })
+            .collect::<Vec<_>>();
+        assert_eq!(tags[0].name, "span");
+        assert_eq!(tags[0].attribute("class"), Some("hw x"));
+        assert!(tags[0].is_open());
+        assert!(tags[1].closing);
+        assert!(!tags[2].is_open());
+        assert_eq!(tags[3].attribute("src"), Some("a.png"));
+        assert!(!tags[3].is_open());
+    }
+
+    #[test]
+    fn entities() {
+        assert_eq!(decode("&lt;&#x4e2d;&#25991;&bogus; &"), "<中文&bogus; &");
+    }
+}
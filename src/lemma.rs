@@ -0,0 +1,333 @@
+/// 不规则的屈折变化, 变化形式和原形
+const IRREGULAR: &[(&str, &str)] = &[
+    // 名词复数
+    ("children", "child"),
+    ("feet", "foot"),
+    ("geese", "goose"),
+    ("lice", "louse"),
+    ("men", "man"),
+    ("mice", "mouse"),
+    ("oxen", "ox"),
+    ("people", "person"),
+    ("teeth", "tooth"),
+    ("women", "woman"),
+    ("analyses", "analysis"),
+    ("crises", "crisis"),
+    ("phenomena", "phenomenon"),
+    ("criteria", "criterion"),
+    ("cacti", "cactus"),
+    ("fungi", "fungus"),
+    // 形容词和副词的比较级, 最高级
+    ("better", "good"),
+    ("better", "well"),
+    ("best", "good"),
+    ("best", "well"),
+    ("worse", "bad"),
+    ("worst", "bad"),
+    ("more", "much"),
+    ("more", "many"),
+    ("most", "much"),
+    ("most", "many"),
+    ("less", "little"),
+    ("least", "little"),
+    ("farther", "far"),
+    ("farthest", "far"),
+    ("further", "far"),
+    ("furthest", "far"),
+    ("elder", "old"),
+    ("eldest", "old"),
+    // 动词
+    ("am", "be"),
+    ("are", "be"),
+    ("is", "be"),
+    ("was", "be"),
+    ("were", "be"),
+    ("been", "be"),
+    ("has", "have"),
+    ("had", "have"),
+    ("does", "do"),
+    ("did", "do"),
+    ("done", "do"),
+    ("went", "go"),
+    ("gone", "go"),
+    ("ate", "eat"),
+    ("eaten", "eat"),
+    ("began", "begin"),
+    ("begun", "begin"),
+    ("bent", "bend"),
+    ("bit", "bite"),
+    ("bitten", "bite"),
+    ("bled", "bleed"),
+    ("blew", "blow"),
+    ("blown", "blow"),
+    ("bought", "buy"),
+    ("bred", "breed"),
+    ("broke", "break"),
+    ("broken", "break"),
+    ("brought", "bring"),
+    ("built", "build"),
+    ("came", "come"),
+    ("caught", "catch"),
+    ("chose", "choose"),
+    ("chosen", "choose"),
+    ("crept", "creep"),
+    ("dealt", "deal"),
+    ("drank", "drink"),
+    ("drunk", "drink"),
+    ("drew", "draw"),
+    ("drawn", "draw"),
+    ("dreamt", "dream"),
+    ("drove", "drive"),
+    ("driven", "drive"),
+    ("dug", "dig"),
+    ("fed", "feed"),
+    ("fell", "fall"),
+    ("fallen", "fall"),
+    ("felt", "feel"),
+    ("fled", "flee"),
+    ("flew", "fly"),
+    ("flown", "fly"),
+    ("forbade", "forbid"),
+    ("forgave", "forgive"),
+    ("forgiven", "forgive"),
+    ("forgot", "forget"),
+    ("forgotten", "forget"),
+    ("fought", "fight"),
+    ("found", "find"),
+    ("froze", "freeze"),
+    ("frozen", "freeze"),
+    ("gave", "give"),
+    ("given", "give"),
+    ("got", "get"),
+    ("gotten", "get"),
+    ("grew", "grow"),
+    ("grown", "grow"),
+    ("heard", "hear"),
+    ("held", "hold"),
+    ("hid", "hide"),
+    ("hidden", "hide"),
+    ("hung", "hang"),
+    ("kept", "keep"),
+    ("knelt", "kneel"),
+    ("knew", "know"),
+    ("known", "know"),
+    ("laid", "lay"),
+    ("lain", "lie"),
+    ("lay", "lie"),
+    ("leapt", "leap"),
+    ("led", "lead"),
+    ("left", "leave"),
+    ("lent", "lend"),
+    ("lit", "light"),
+    ("lost", "lose"),
+    ("made", "make"),
+    ("meant", "mean"),
+    ("met", "meet"),
+    ("mistook", "mistake"),
+    ("mistaken", "mistake"),
+    ("paid", "pay"),
+    ("ran", "run"),
+    ("rode", "ride"),
+    ("ridden", "ride"),
+    ("rose", "rise"),
+    ("risen", "rise"),
+    ("sang", "sing"),
+    ("sung", "sing"),
+    ("sank", "sink"),
+    ("sunk", "sink"),
+    ("sat", "sit"),
+    ("saw", "see"),
+    ("seen", "see"),
+    ("said", "say"),
+    ("sold", "sell"),
+    ("sent", "send"),
+    ("shook", "shake"),
+    ("shaken", "shake"),
+    ("shot", "shoot"),
+    ("slept", "sleep"),
+    ("slid", "slide"),
+    ("sought", "seek"),
+    ("spent", "spend"),
+    ("spoke", "speak"),
+    ("spoken", "speak"),
+    ("stood", "stand"),
+    ("stole", "steal"),
+    ("stolen", "steal"),
+    ("struck", "strike"),
+    ("stuck", "stick"),
+    ("swam", "swim"),
+    ("swum", "swim"),
+    ("swept", "sweep"),
+    ("swung", "swing"),
+    ("taught", "teach"),
+    ("took", "take"),
+    ("taken", "take"),
+    ("thought", "think"),
+    ("threw", "throw"),
+    ("thrown", "throw"),
+    ("told", "tell"),
+    ("understood", "understand"),
+    ("wept", "weep"),
+    ("woke", "wake"),
+    ("woken", "wake"),
+    ("won", "win"),
+    ("wore", "wear"),
+    ("worn", "wear"),
+    ("wound", "wind"),
+    ("wrote", "write"),
+    ("written", "write"),
+];
+
+/// 规则变化的后缀和还原时替换成的内容, 按可能性从高到低排列.
+/// 以 `e` 结尾的原形优先, 否则 `using`, `hoped` 和 `notes` 会先找到 `us`, `hop` 和 `not`
+const RULES: &[(&str, &str)] = &[
+    ("ies", "y"),
+    ("ied", "y"),
+    ("ier", "y"),
+    ("iest", "y"),
+    ("ily", "y"),
+    ("ves", "f"),
+    ("ves", "fe"),
+    ("s", ""),
+    ("es", ""),
+    ("ing", "e"),
+    ("ing", ""),
+    ("ed", "e"),
+    ("ed", ""),
+    ("er", "e"),
+    ("er", ""),
+    ("est", "e"),
+    ("est", ""),
+    ("ly", ""),
+    ("men", "man"),
+];
+
+/// 去掉这些后缀时, 重复的辅音字母可能是变化时加上的, 如 `running`, `stopped`, `bigger`
+const DOUBLED: &[&str] = &["ing", "ed", "er", "est"];
+
+/// 以 `s` 等后缀结尾但没有屈折变化的单词
+const UNINFLECTED: &[&str] = &[
+    "news",
+    "series",
+    "species",
+    "means",
+    "physics",
+    "mathematics",
+    "economics",
+    "politics",
+    "ethics",
+    "measles",
+    "diabetes",
+];
+
+/// 英语单词可能的原形, 按可能性分组: 所有格去掉 `'s` 的单词, 不规则变化, 然后每条规则一组.
+/// 查找时应当在第一个有结果的组停下, 避免 `better` 在找到 `good` 之后又按规则找到 `bet`.
+/// 不包括 `word` 本身, 只处理由英文字母组成的单词, 其他的返回空
+pub(crate) fn lemmas(word: &str) -> Vec<Vec<String>> {
+    let lower = word.trim().to_ascii_lowercase();
+    let (word, possessive) = match lower
+        .strip_suffix("'s")
+        .or_else(|| lower.strip_suffix("’s"))
+    {
+        Some(v) => (v, true),
+        None => (lower.as_str(), false),
+    };
+    if word.is_empty()
+        || !word.bytes().all(|c| c.is_ascii_lowercase() || c == b'-')
+        || UNINFLECTED.contains(&word)
+    {
+        return Vec::new();
+    }
+
+    let mut tiers = Vec::new();
+    if possessive {
+        tiers.push(vec![word.to_string()]);
+    }
+    tiers.push(
+        IRREGULAR
+            .iter()
+            .filter(|v| v.0 == word)
+            .map(|v| v.1.to_string())
+            .collect(),
+    );
+
+    for (suffix, replacement) in RULES {
+        let stem = match word.strip_suffix(suffix) {
+            Some(v) if is_stem(v) => v,
+            _ => continue,
+        };
+        let mut tier = vec![format!("{}{}", stem, replacement)];
+
+        let bytes = stem.as_bytes();
+        let n = bytes.len();
+        if replacement.is_empty()
+            && DOUBLED.contains(suffix)
+            && n >= 3
+            && bytes[n - 1] == bytes[n - 2]
+            && !b"aeiou".contains(&bytes[n - 1])
+        {
+            tier.push(stem[..n - 1].to_string());
+        }
+        tiers.push(tier);
+    }
+
+    // 每个候选只保留第一次出现
+    let mut seen: Vec<String> = vec![lower.clone()];
+    tiers
+        .into_iter()
+        .map(|tier| {
+            tier.into_iter()
+                .filter(|v| {
+                    let new = !seen.contains(v);
+                    if new {
+                        seen.push(v.clone());
+                    }
+                    new
+                })
+                .collect::<Vec<_>>()
+        })
+        .filter(|v| !v.is_empty())
+        .collect()
+}
+
+/// 至少两个字母并且包含元音, 避免 `thing` 还原为 `th` 这样的结果
+fn is_stem(stem: &str) -> bool {
+    stem.len() >= 2 && stem.bytes().any(|c| b"aeiouy".contains(&c))
+}
+
+#[cfg(test)]
+mod tests {
+    use super::lemmas;
+
+    #[test]
+    fn irregular() {
+        assert_eq!(lemmas("better")[0], ["good", "well"]);
+        assert_eq!(lemmas("geese")[0], ["goose"]);
+        assert_eq!(lemmas("was")[0], ["be"]);
+    }
+
+    #[test]
+    fn doubled() {
+        assert_eq!(lemmas("running")[0], ["runne"]);
+        assert_eq!(lemmas("running")[1], ["runn", "run"]);
+        assert_eq!(lemmas("stopped")[1], ["stopp", "stop"]);
+    }
+
+    #[test]
+    fn silent_e() {
+        assert_eq!(lemmas("using")[..2], [["use"], ["us"]]);
+        assert_eq!(lemmas("notes")[..2], [["note"], ["not"]]);
+    }
+
+    #[test]
+    fn uninflected() {
+        assert!(lemmas("news").is_empty());
+        assert!(lemmas("café").is_empty());
+    }
+
+    #[test]
+    fn possessive() {
+        assert_eq!(lemmas("dog's")[0], ["dog"]);
+        assert_eq!(lemmas("Children's")[..2], [["children"], ["child"]]);
+    }
+}
@@ -1 +1,24 @@
+mod dictionary;
+mod export;
+mod fulltext;
+mod fuzzy;
+mod html;
+mod lemma;
+mod links;
+mod markdown;
 pub mod mdict;
+mod pattern;
+mod reverse;
+mod terminal;
+
+pub use dictionary::{Dictionary, Entry};
+pub use export::Export;
+pub use fulltext::Hit;
+pub use fuzzy::Distance;
+pub use links::LinkOptions;
+pub use mdict::{
+    Checksum, DictMeta, Error, KeyNormalizer, Location, ParseOptions, RegCode, Result, Section,
+    StyleSheet, WarningHandler,
+};
+pub use pattern::Pattern;
+pub use terminal::TerminalOptions;
@@ -0,0 +1,193 @@
+use crate::dictionary::Entry;
+use crate::html::{self, attributes, decode, Tag, Token};
+
+/// 把词条中 MDict 专用的链接改写为普通的 URL.
+/// 模板中的 `{word}` 替换为词条, `{path}` 替换为资源的路径, 都按百分号编码
+#[derive(Debug, Clone, PartialEq)]
+pub struct LinkOptions {
+    /// `entry://word` 和 `bword://word`, 如 `/lookup/{word}`
+    pub entry: String,
+    /// `sound://uk/hello.spx`, 如 `/res/{path}`
+    pub sound: String,
+    /// `src="img/x.png"` 以及 `<link href="x.css">` 这样的相对路径
+    pub resource: String,
+}
+
+impl Default for LinkOptions {
+    fn default() -> Self {
+        LinkOptions {
+            entry: "/lookup/{word}".to_string(),
+            sound: "/res/{path}".to_string(),
+            resource: "/res/{path}".to_string(),
+        }
+    }
+}
+
+impl LinkOptions {
+    /// 改写 HTML 中 `href` 和 `src` 属性的链接, 其他内容保持不变.
+    /// `entry://#id` 这样指向词条内部的链接改为 `#id`, 网址和 `data:` 等不改写
+    pub fn rewrite(&self, html: &str) -> String {
+        rewrite(html, &mut &*self)
+    }
+}
+
+impl Rewriter for &LinkOptions {
+    fn attribute(&mut self, name: &str, attribute: &str, value: &str) -> Option<String> {
+        if attribute != "href" && attribute != "src" {
+            return None;
+        }
+        let lower = value.to_ascii_lowercase();
+
+        if let Some((word, fragment)) = entry(value) {
+            if word.is_empty() {
+                return Some(fragment.to_string());
+            }
+            let word = encode(word, false);
+            Some(format!(
+                "{}{}",
+                self.entry.replace("{word}", &word),
+                fragment
+            ))
+        } else if lower.starts_with("sound://") {
+            Some(
+                self.sound
+                    .replace("{path}", &encode(&path(&value[8..]), true)),
+            )
+        } else if is_resource(name, attribute, value) {
+            Some(self.resource.replace("{path}", &encode(&path(value), true)))
+        } else {
+            None
+        }
+    }
+}
+
+/// 改写 HTML 中的链接和样式
+pub(crate) trait Rewriter {
+    /// 元素 `name` 的属性 `attribute` 改写后的值, `value` 已经解码了字符实体. 返回 `None` 时保持不变
+    fn attribute(&mut self, name: &str, attribute: &str, value: &str) -> Option<String>;
+
+    /// `<style>` 元素的内容改写后的结果
+    fn style(&mut self, css: &str) -> Option<String> {
+        let _ = css;
+        None
+    }
+}
+
+/// 依次改写每个标签的属性和 `<style>` 的内容, 注释和脚本原样保留
+pub(crate) fn rewrite(html: &str, rewriter: &mut impl Rewriter) -> String {
+    let mut text = String::with_capacity(html.len());
+    let mut style = false;
+
+    for token in html::tokens(html) {
+        match token {
+            Token::Tag(tag) if !tag.name.is_empty() => {
+                style = !tag.closing && tag.name == "style";
+                text.push_str(&rewrite_tag(&tag, rewriter));
+            }
+            Token::Raw(css) if style => {
+                text.push_str(rewriter.style(css).as_deref().unwrap_or(css))
+            }
+            Token::Tag(Tag { text: v, .. })
+            | Token::Text(v)
+            | Token::Comment(v)
+            | Token::Raw(v) => text.push_str(v),
+        }
+    }
+
+    text
+}
+
+fn rewrite_tag(tag: &Tag, rewriter: &mut impl Rewriter) -> String {
+    let (name, tag) = (tag.name.as_str(), tag.text);
+    let mut text = String::with_capacity(tag.len());
+    let mut last = 0;
+
+    for (attribute, range) in attributes(tag) {
+        let value = match rewriter.attribute(name, &attribute, &decode(&tag[range.clone()])) {
+            Some(v) => v,
+            None => continue,
+        };
+
+        // 原本没有引号的值也加上引号
+        let quoted = tag[..range.start].ends_with(['"', '\'']);
+        text.push_str(&tag[last..range.start]);
+        if !quoted {
+            text.push('"');
+        }
+        text.push_str(&escape(&value));
+        if !quoted {
+            text.push('"');
+        }
+        last = range.end;
+    }
+    text.push_str(&tag[last..]);
+
+    text
+}
+
+impl Entry {
+    /// 按 `options` 改写释义中的链接
+    pub fn rewrite_links(&self, options: &LinkOptions) -> String {
+        options.rewrite(&self.definition)
+    }
+}
+
+/// `entry://word#id` 和 `bword://word` 中的词条和 `#id`
+pub(crate) fn entry(url: &str) -> Option<(&str, &str)> {
+    let lower = url.to_ascii_lowercase();
+    let word = ["entry://", "bword://"]
+        .iter()
+        .find(|v| lower.starts_with(*v))
+        .map(|v| &url[v.len()..])?;
+    let (word, fragment) = word.split_at(word.find('#').unwrap_or(word.len()));
+    Some((word.trim_end_matches('/'), fragment))
+}
+
+/// `src` 以及 `<link href>` 中的相对路径指向词典的资源
+pub(crate) fn is_resource(name: &str, attribute: &str, url: &str) -> bool {
+    (attribute == "src" || (attribute == "href" && name == "link"))
+        && !url.is_empty()
+        && !is_absolute(url)
+}
+
+/// 带有协议, 以 `//` 开头或者只有 `#id` 的链接. 以 `/` 开头的路径在词典中同样指向资源
+pub(crate) fn is_absolute(url: &str) -> bool {
+    let scheme = url
+        .split(':')
+        .next()
+        .filter(|v| v.len() < url.len())
+        .filter(|v| !v.is_empty())
+        .filter(|v| {
+            v.chars()
+                .all(|c| c.is_ascii_alphanumeric() || "+-.".contains(c))
+        });
+    scheme.is_some() || url.starts_with("//") || url.starts_with('#')
+}
+
+/// 资源的路径. MDD 中的路径以 `\` 分隔, 这里统一为 `/` 并去掉开头的 `./` 和 `/`
+pub(crate) fn path(path: &str) -> String {
+    let path = path.replace('\\', "/");
+    let mut path = path.as_str();
+    while let Some(v) = path.strip_prefix("./").or_else(|| path.strip_prefix('/')) {
+        path = v;
+    }
+    path.to_string()
+}
+
+/// 属性值中的 `&` 和 `"`
+pub(crate) fn escape(value: &str) -> String {
+    value.replace('&', "&amp;").replace('"', "&quot;")
+}
+
+/// 按百分号编码 `text` 的 UTF-8 字节, `path` 为 `true` 时保留 `/`
+pub(crate) fn encode(text: &str, path: bool) -> String {
+    let mut encoded = String::with_capacity(text.len());
+    for &c in text.as_bytes() {
+        if c.is_ascii_alphanumeric() || b"-._~".contains(&c) || (path && c == b'/') {
+            encoded.push(c as char);
+        } else {
+            encoded.push_str(&format!("%{:02X}", c));
+        }
+    }
+    encoded
+}
@@ -0,0 +1,372 @@
+use crate::dictionary::Entry;
+use crate::html::{self, is_example, is_headword, Tag, Token};
+
+/// 前后需要分段的元素
+const BLOCK: &[&str] = &[
+    "address", "article", "aside", "dd", "div", "dl", "dt", "figure", "footer", "header", "p",
+    "section", "table", "tr",
+];
+
+/// Markdown 中有特殊含义, 出现在文本中时需要转义的字符
+const ESCAPE: &[char] = &['\\', '`', '*', '_', '[', ']', '<', '>', '#'];
+
+/// 元素在 Markdown 中的写法
+enum Kind {
+    Inline,
+    Block,
+    Heading,
+    Strong,
+    Emphasis,
+    Link,
+    List,
+    Item,
+    Quote,
+}
+
+/// 段落所在的引用和列表项
+enum Container {
+    Quote,
+    /// 列表项的缩进, 以及还没有输出的标记
+    Item(usize, Option<String>),
+}
+
+/// 行内的 `**`, `*` 和链接. 开始的标记在遇到第一个非空白字符时才输出, 避免出现 `** word**` 或者空的标记
+struct Mark {
+    start: Option<usize>,
+    open: &'static str,
+    close: String,
+}
+
+#[derive(Default)]
+struct Writer {
+    text: String,
+    // 正在收集的段落, `\n` 表示段落内的换行
+    line: String,
+    containers: Vec<Container>,
+    // 有序列表的下一个序号, 无序列表为 `None`
+    lists: Vec<Option<u32>>,
+    marks: Vec<Mark>,
+    strong: usize,
+    emphasis: usize,
+}
+
+impl Writer {
+    fn push(&mut self, c: char) {
+        if c.is_whitespace() {
+            if !self.line.is_empty() && !self.line.ends_with([' ', '\n']) {
+                self.line.push(' ');
+            }
+            return;
+        }
+
+        for mark in self.marks.iter_mut().filter(|v| v.start.is_none()) {
+            mark.start = Some(self.line.len());
+            self.line.push_str(mark.open);
+        }
+        if ESCAPE.contains(&c) {
+            self.line.push('\\');
+        }
+        self.line.push(c);
+    }
+
+    fn line_break(&mut self) {
+        let len = self.line.trim_end_matches(' ').len();
+        self.line.truncate(len);
+        if !self.line.is_empty() && !self.line.ends_with('\n') {
+            self.line.push('\n');
+        }
+    }
+
+    fn open(&mut self, open: &'static str, close: String) {
+        self.marks.push(Mark {
+            start: None,
+            open,
+            close,
+        });
+    }
+
+    fn close(&mut self) {
+        if let Some(mark) = self.marks.pop() {
+            self.finish(&mark);
+        }
+    }
+
+    fn finish(&mut self, mark: &Mark) {
+        if mark.start.is_none() {
+            return;
+        }
+        // 结束的标记放在末尾的空白之前
+        let len = self.line.trim_end_matches([' ', '\n']).len();
+        let rest = self.line.split_off(len);
+        self.line.push_str(&mark.close);
+        self.line.push_str(&rest);
+    }
+
+    /// 输出正在收集的段落, 没有结束的行内标记在下一段重新开始
+    fn flush(&mut self) {
+        let marks = std::mem::take(&mut self.marks);
+        for mark in marks.iter().rev() {
+            self.finish(mark);
+        }
+        self.marks = marks
+            .into_iter()
+            .map(|v| Mark { start: None, ..v })
+            .collect();
+
+        let line = std::mem::take(&mut self.line);
+        let lines = line
+            .split('\n')
+            .map(|v| v.trim())
+            .filter(|v| !v.is_empty())
+            .collect::<Vec<_>>();
+        if lines.is_empty() {
+            return;
+        }
+
+        if !self.text.is_empty() {
+            self.text.push('\n');
+        }
+        for (i, line) in lines.iter().enumerate() {
+            self.prefix(i == 0);
+            self.text.push_str(&block_start(line));
+            if i + 1 < lines.len() {
+                self.text.push('\\');
+            }
+            self.text.push('\n');
+        }
+    }
+
+    /// 引用的 `> ` 和列表项的缩进, 列表项的第一行为序号或 `- `
+    fn prefix(&mut self, first: bool) {
+        for container in &mut self.containers {
+            match container {
+                Container::Quote => self.text.push_str("> "),
+                Container::Item(width, marker) => match marker.take().filter(|_| first) {
+                    Some(marker) => self.text.push_str(&marker),
+                    None => self.text.push_str(&" ".repeat(*width)),
+                },
+            }
+        }
+    }
+
+    fn start(&mut self, tag: &Tag) -> Kind {
+        let class = tag.attribute("class").unwrap_or_default();
+
+        match tag.name.as_str() {
+            "ul" | "ol" => {
+                self.flush();
+                self.lists.push(if tag.name == "ol" {
+                    Some(
+                        tag.attribute("start")
+                            .and_then(|v| v.parse().ok())
+                            .unwrap_or(1),
+                    )
+                } else {
+                    None
+                });
+                Kind::List
+            }
+            "li" => {
+                self.flush();
+                let marker = match self.lists.last_mut() {
+                    Some(Some(n)) => {
+                        let marker = format!("{}. ", n);
+                        // 序号到了上限之后不再增加
+                        *n = n.saturating_add(1);
+                        marker
+                    }
+                    _ => "- ".to_string(),
+                };
+                self.containers
+                    .push(Container::Item(marker.len(), Some(marker)));
+                Kind::Item
+            }
+            "blockquote" => self.quote(),
+            // 例句中的例句不再嵌套引用
+            _ if is_example(class) && !matches!(self.containers.last(), Some(Container::Quote)) => {
+                self.quote()
+            }
+            "h1" | "h2" | "h3" | "h4" | "h5" | "h6" => {
+                self.flush();
+                self.open("**", "**".to_string());
+                Kind::Heading
+            }
+            "b" | "strong" => self.strong(),
+            _ if is_headword(class) => self.strong(),
+            "i" | "em" => {
+                self.emphasis += 1;
+                if self.emphasis == 1 {
+                    self.open("*", "*".to_string());
+                }
+                Kind::Emphasis
+            }
+            "a" => match tag.attribute("href").and_then(|v| link(&html::decode(v))) {
+                Some(url) => {
+                    self.open("[", format!("]({})", url));
+                    Kind::Link
+                }
+                None => Kind::Inline,
+            },
+            name if BLOCK.contains(&name) => {
+                self.flush();
+                Kind::Block
+            }
+            _ => Kind::Inline,
+        }
+    }
+
+    fn quote(&mut self) -> Kind {
+        self.flush();
+        self.containers.push(Container::Quote);
+        Kind::Quote
+    }
+
+    fn strong(&mut self) -> Kind {
+        self.strong += 1;
+        if self.strong == 1 {
+            self.open("**", "**".to_string());
+        }
+        Kind::Strong
+    }
+
+    fn end(&mut self, kind: Kind) {
+        match kind {
+            Kind::Inline => {}
+            Kind::Block => self.flush(),
+            Kind::Heading => {
+                self.close();
+                self.flush();
+            }
+            Kind::Strong => {
+                self.strong -= 1;
+                if self.strong == 0 {
+                    self.close();
+                }
+            }
+            Kind::Emphasis => {
+                self.emphasis -= 1;
+                if self.emphasis == 0 {
+                    self.close();
+                }
+            }
+            Kind::Link => self.close(),
+            Kind::List => {
+                self.flush();
+                self.lists.pop();
+            }
+            Kind::Item | Kind::Quote => {
+                self.flush();
+                self.containers.pop();
+            }
+        }
+    }
+}
+
+/// `entry://` 和 `bword://` 转换为相对链接, 保留网址, 其他的如 `sound://` 只保留文本
+fn link(href: &str) -> Option<String> {
+    let lower = href.to_ascii_lowercase();
+    if let Some(word) = ["entry://", "bword://"]
+        .iter()
+        .find(|v| lower.starts_with(*v))
+        .map(|v| &href[v.len()..])
+    {
+        let (word, fragment) = word.split_at(word.find('#').unwrap_or(word.len()));
+        Some(format!("{}{}", encode(word), fragment))
+    } else if ["http://", "https://", "mailto:"]
+        .iter()
+        .any(|v| lower.starts_with(v))
+    {
+        Some(encode(href))
+    } else {
+        None
+    }
+}
+
+/// 链接中的空白, 括号等 ASCII 字符按百分号编码, 其他字符保持不变
+fn encode(url: &str) -> String {
+    let mut encoded = String::with_capacity(url.len());
+    for c in url.chars() {
+        if c.is_ascii_whitespace() || c.is_ascii_control() || "()<>\\\"".contains(c) {
+            encoded.push_str(&format!("%{:02X}", c as u8));
+        } else {
+            encoded.push(c);
+        }
+    }
+    encoded
+}
+
+/// 转义行首的 `-`, `+` 和 `1.`, 避免被当作列表
+fn block_start(line: &str) -> String {
+    if line.starts_with(['-', '+']) {
+        return format!("\\{}", line);
+    }
+    let digits = line.len() - line.trim_start_matches(|c: char| c.is_ascii_digit()).len();
+    if digits > 0 && line[digits..].starts_with(['.', ')']) {
+        return format!("{}\\{}", &line[..digits], &line[digits..]);
+    }
+    line.to_string()
+}
+
+/// 把词条的 HTML 转换为 CommonMark, 不输出脚本, 样式和图片
+fn markdown(html: &str) -> String {
+    let mut writer = Writer::default();
+    // 打开的元素和它在 Markdown 中的写法
+    let mut stack: Vec<(String, Kind)> = Vec::new();
+
+    for token in html::tokens(html) {
+        let tag = match token {
+            Token::Text(v) => {
+                html::decode(v).chars().for_each(|c| writer.push(c));
+                continue;
+            }
+            Token::Tag(tag) => tag,
+            Token::Comment(_) | Token::Raw(_) => continue,
+        };
+
+        if tag.closing {
+            if let Some(i) = stack.iter().rposition(|v| v.0 == tag.name) {
+                for (_, kind) in stack.drain(i..).rev() {
+                    writer.end(kind);
+                }
+            }
+        } else if tag.name == "br" {
+            writer.line_break();
+        } else if tag.name == "hr" {
+            writer.flush();
+        } else if tag.is_open() {
+            let kind = writer.start(&tag);
+            stack.push((tag.name, kind));
+        }
+    }
+    for (_, kind) in stack.into_iter().rev() {
+        writer.end(kind);
+    }
+    writer.flush();
+
+    writer.text
+}
+
+impl Entry {
+    /// 渲染为 CommonMark. 第一段为加粗的词头, 经过 `@@@LINK=` 跳转时附上跳转的过程.
+    /// 列表为义项, 例句为引用, `entry://` 的链接为指向词条的相对链接
+    pub fn to_markdown(&self) -> String {
+        let mut writer = Writer::default();
+        writer.open("**", "**".to_string());
+        self.key.chars().for_each(|c| writer.push(c));
+        writer.close();
+        if !self.redirects.is_empty() {
+            writer.push(' ');
+            writer.open("*", "*".to_string());
+            self.chain().chars().for_each(|c| writer.push(c));
+            writer.close();
+        }
+        writer.flush();
+
+        let definition = markdown(&self.definition);
+        if !definition.is_empty() {
+            writer.text.push('\n');
+            writer.text.push_str(&definition);
+        }
+        writer.text
+    }
+}
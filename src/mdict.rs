@@ -1,317 +1,743 @@
-use binread::io::Cursor;
-use binread::{prelude::*, NullString, NullWideString, ReadOptions};
-use byteorder::{LittleEndian, WriteBytesExt};
-use flate2::read::ZlibDecoder;
+use std::{
+    borrow::Cow,
+    collections::HashMap,
+    fmt, fs,
+    fs::File,
+    io,
+    ops::Deref,
+    path::{Path, PathBuf},
+    result,
+    string::FromUtf16Error,
+    sync::Arc,
+};
+
+use adler::adler32_slice;
+use encoding_rs::{Encoding, GB18030, GBK, UTF_8};
+use memmap2::Mmap;
+use nom::{
+    error::{ErrorKind, FromExternalError, ParseError},
+    multi::length_data,
+    number::streaming::{be_u32, le_u32},
+    sequence::tuple,
+    IResult, Parser,
+};
 use ripemd128::{Digest, Ripemd128};
 use serde::Deserialize;
-use std::collections::HashMap;
-use std::fs::File;
-use std::io::SeekFrom;
-use std::io::{self, prelude::*};
-use std::path::Path;
-use std::u32;
-use std::usize;
+
 use thiserror::Error;
+use xxhash_rust::xxh64::xxh64;
 
 #[derive(Error, Debug)]
 pub enum Error {
     #[error("{0}")]
-    IO(#[from] io::Error),
+    De(#[from] quick_xml::DeError),
     #[error("{0}")]
-    BinRead(#[from] binread::Error),
+    FromUtf16(#[from] FromUtf16Error),
     #[error("{0}")]
-    De(#[from] quick_xml::DeError),
+    IO(#[from] io::Error),
+    #[error("{0}")]
+    Lzo(#[from] minilzo_rs::Error),
+    #[error("malformed data ({0:?})")]
+    Nom(ErrorKind),
+    #[error("unexpected end of file, the dictionary may be truncated")]
+    Truncated,
+    #[error("dictionary is encrypted, a registration code is required")]
+    Encrypted,
+    #[error("invalid registration code")]
+    InvalidRegCode,
+    #[error("unknown encoding {0}")]
+    UnknownEncoding(String),
+    #[error("invalid {0} text")]
+    Decode(&'static str),
+    #[error("unsupported engine version {0}")]
+    UnsupportedVersion(f64),
+    #[error("unknown block type {0:#x}")]
+    UnknownBlockType(u32),
+    #[error("unknown encryption method {0}")]
+    UnknownEncryption(u32),
+    #[error("header checksum mismatch")]
+    HeaderChecksum,
+    #[error("key block header checksum mismatch")]
+    KeyHeaderChecksum,
+    #[error("checksum mismatch")]
+    Checksum,
+    #[error("record offset {0:#x} out of range")]
+    InvalidOffset(u64),
+    #[error("{0} {1:#x} exceeds the available data")]
+    InvalidSize(&'static str, u64),
+    #[error("invalid pattern: {0}")]
+    Pattern(#[from] regex::Error),
+    #[error("{location}: {source}")]
+    Parse {
+        location: Location,
+        source: Box<Error>,
+    },
+    #[error("failed to save {}: {source}", path.display())]
+    Save { path: PathBuf, source: Box<Error> },
+}
+
+impl Error {
+    /// 已经带有位置的错误保留最内层的位置
+    fn at(self, location: Location) -> Self {
+        match self {
+            Error::Parse { .. } => self,
+            e => Error::Parse {
+                location,
+                source: Box::new(e),
+            },
+        }
+    }
 }
 
-type Result<T> = std::result::Result<T, Error>;
+impl From<nom::Err<Error>> for Error {
+    fn from(e: nom::Err<Error>) -> Self {
+        match e {
+            nom::Err::Incomplete(_) => Error::Truncated,
+            nom::Err::Error(e) | nom::Err::Failure(e) => e,
+        }
+    }
+}
+
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub enum Section {
+    Header,
+    KeyInfo,
+    KeyBlock,
+    RecordBlock,
+}
 
-#[derive(Debug, BinRead)]
-pub struct Mdx {
-    #[br(big)]
-    n_dict_meta: u32,
-    #[br(little, try_map(|data: NullWideString| parse_dict_meta(data)))]
-    dict: DictMeta,
-    #[br(little)]
-    checksum: u32,
+impl fmt::Display for Section {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        f.write_str(match self {
+            Section::Header => "header",
+            Section::KeyInfo => "key-info",
+            Section::KeyBlock => "key-block",
+            Section::RecordBlock => "record-block",
+        })
+    }
+}
 
-    key_block: MdxKeyBlock,
-    record_block: MdxRecordBlock,
+/// 出错的位置, `offset` 为所在 section 或 block 在文件中的偏移
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub struct Location {
+    pub section: Section,
+    pub index: Option<usize>,
+    pub offset: usize,
 }
 
-fn parse_dict_meta(data: NullWideString) -> Result<DictMeta> {
-    let dict = quick_xml::de::from_str::<DictMeta>(&data.to_string())?;
-    println!("{:?}", dict);
-    Ok(dict)
+impl fmt::Display for Location {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        write!(f, "{}", self.section)?;
+        if let Some(index) = self.index {
+            write!(f, " block {}", index)?;
+        }
+        write!(f, " at offset {:#x}", self.offset)
+    }
 }
 
-impl Mdx {
-    pub fn search(&self, text: String) -> Vec<(String, String)> {
-        self.key_block
-            .entries
-            .iter()
-            .filter(|item| item.0.contains(&text))
-            .map(|item| (item.0.clone(), self.record_block.record(*item.1)))
-            .collect::<_>()
+/// 给 parser 的错误加上位置, `Incomplete` 视为文件被截断
+fn located<I, O>(location: Location, result: NomResult<I, O>) -> NomResult<I, O> {
+    result.map_err(|e| nom::Err::Failure(Error::from(e).at(location)))
+}
+
+impl<I> ParseError<I> for Error {
+    fn from_error_kind(_input: I, kind: ErrorKind) -> Self {
+        Error::Nom(kind)
+    }
+
+    fn append(_: I, _: ErrorKind, other: Self) -> Self {
+        other
+    }
+}
+
+impl<I> FromExternalError<I, Error> for Error {
+    fn from_external_error(_input: I, _kind: ErrorKind, e: Error) -> Self {
+        e
     }
 }
 
+pub type Result<T> = result::Result<T, Error>;
+type NomResult<I, O> = nom::IResult<I, O, Error>;
+
+/// header 中的词典信息
 #[derive(Debug, Deserialize, PartialEq)]
-struct DictMeta {
+pub struct DictMeta {
     #[serde(rename = "GeneratedByEngineVersion")]
-    generated_by_engine_version: f64,
+    pub generated_by_engine_version: f64,
     #[serde(rename = "RequiredEngineVersion")]
-    required_engine_version: f64,
-    #[serde(rename = "Format")]
-    format: String,
+    pub required_engine_version: f64,
+    #[serde(rename = "Format", default)]
+    pub format: String,
     #[serde(rename = "KeyCaseSensitive")]
-    key_case_sensitive: String,
+    pub key_case_sensitive: String,
     #[serde(rename = "StripKey")]
-    strip_key: Option<String>,
+    pub strip_key: Option<String>,
     #[serde(rename = "Encrypted")]
-    encrypted: String,
+    pub encrypted: String,
     #[serde(rename = "RegisterBy")]
-    register_by: Option<String>,
-    #[serde(rename = "Description")]
-    description: String,
+    pub register_by: Option<String>,
+    #[serde(rename = "Description", default)]
+    pub description: String,
     #[serde(rename = "Title")]
-    title: String,
+    pub title: String,
     #[serde(rename = "Encoding")]
-    encoding: String,
+    pub encoding: String,
     #[serde(rename = "CreationDate")]
-    creation_date: Option<String>,
-    #[serde(rename = "Compact")]
-    compact: String,
-    #[serde(rename = "Compat")]
-    compat: String,
-    #[serde(rename = "Left2Right")]
-    left2right: String,
-    #[serde(rename = "DataSourceFormat")]
-    data_source_format: String,
-    #[serde(rename = "StyleSheet")]
-    style_sheet: String,
-}
-
-impl Mdx {
-    pub fn parse(path: &Path) -> Result<Mdx> {
-        let mut file = File::open(path)?;
-        Ok(Mdx::read(&mut file)?)
-    }
-}
-
-type KeyMap = HashMap<String, u64>;
-
-#[derive(Debug, BinRead)]
-struct MdxKeyBlock {
-    #[br(big)]
-    pub n_blocks: u64,
-    #[br(big)]
-    pub n_entires: u64,
-    #[br(big)]
-    pub nb_decompressed: u64,
-    #[br(big)]
-    pub nb_info: u64,
-    #[br(big)]
-    pub nb_blocks: u64,
-    #[br(little)]
-    pub checksum: u32,
-
-    #[br(args(nb_info, n_blocks))]
-    info: MdxKeyBlockInfo,
-    #[br(count(n_blocks))]
-    #[br(parse_with = |reader: &mut R, _: &ReadOptions, _: ()| -> BinResult<KeyMap> { parse_key_entries(reader, &info) })]
-    entries: KeyMap,
-}
-
-#[derive(Debug, BinRead)]
-#[br(import(nb_info: u64, n_blocks: u64))]
-#[br(magic = 0x2u32)]
-struct MdxKeyBlockInfo {
-    #[br(little)]
-    checksum: u32,
-    #[br(count(nb_info - 8), try_map = |data: Vec<u8>| parse_key_block_info(data, n_blocks, checksum))]
-    data: Vec<MdxKeyBlockInfoItem>,
-}
-
-#[derive(Debug)]
-struct MdxKeyBlockInfoItem {
-    n_entries: u64,
-    nb_compressed: u64,
-    nb_decompressed: u64,
-}
-
-fn parse_key_block_info(
-    mut input: Vec<u8>,
-    n_blocks: u64,
-    checksum: u32,
-) -> Result<Vec<MdxKeyBlockInfoItem>> {
-    let key: Vec<u8>;
-    {
-        let mut vec = Vec::with_capacity(8);
-        vec.write_u32::<LittleEndian>(checksum)?;
-        vec.write_u32::<LittleEndian>(0x3695)?;
-
-        let mut hasher = Ripemd128::new();
-        hasher.input(vec);
-        key = hasher.result().to_vec();
+    pub creation_date: Option<String>,
+    #[serde(rename = "Compact", default)]
+    pub compact: String,
+    #[serde(rename = "Compat", default)]
+    pub compat: String,
+    #[serde(rename = "Left2Right", default)]
+    pub left2right: String,
+    #[serde(rename = "DataSourceFormat", default)]
+    pub data_source_format: String,
+    #[serde(rename = "StyleSheet", default)]
+    pub style_sheet: String,
+    #[serde(rename = "UUID")]
+    pub uuid: Option<String>,
+}
+
+#[derive(Debug, Clone, Copy, PartialEq)]
+enum Version {
+    V1,
+    V2,
+    V3,
+}
+
+impl DictMeta {
+    fn is_ver2(&self) -> bool {
+        self.required_engine_version >= 2.0
     }
 
-    let mut prev = 0x36;
-    input.iter_mut().enumerate().for_each(|(i, b)| {
-        let mut t = (*b >> 4 | *b << 4) & 0xff;
-        t = t ^ prev ^ (i & 0xff) as u8 ^ key[i % key.len()];
-
-        prev = *b;
-        *b = t;
-    });
-
-    let mut data = Vec::new();
-    {
-        let mut decoder = ZlibDecoder::new(Cursor::new(input));
-        decoder.read_to_end(&mut data)?;
-    }
-
-    let mut cursor = Cursor::new(&data);
-
-    let mut vec: Vec<MdxKeyBlockInfoItem> = Vec::with_capacity(n_blocks as usize);
-
-    for _ in 0..n_blocks {
-        let n_entries: u64 = cursor.read_be()?;
-
-        let head: u16 = cursor.read_be()?;
-        cursor.seek(SeekFrom::Current((head + 1).into()))?;
-        let tail: u16 = cursor.read_be()?;
-        cursor.seek(SeekFrom::Current((tail + 1).into()))?;
-        let nb_compressed = cursor.read_be::<u64>()?;
-        let nb_decompressed = cursor.read_be::<u64>()?;
-
-        vec.push(MdxKeyBlockInfoItem {
-            n_entries,
-            nb_compressed,
-            nb_decompressed,
-        });
-    }
-
-    Ok(vec)
-}
-
-#[derive(Debug, BinRead)]
-#[br(little, repr = u32)]
-enum ContentBlockType {
-    UnCompressed = 0,
-    LZO = 1,
-    Zlib = 2,
-}
-
-#[derive(Debug, BinRead)]
-#[br(import(nb_compressed: u64, nb_decompressed: u64))]
-struct MdxContentBlock {
-    block_type: ContentBlockType,
-    #[br(little)]
-    checksum: u32,
-    #[br(parse_with = |reader: &mut R, _: &ReadOptions, _: ()| -> BinResult<Vec<u8>> { parse_content_block(reader, &block_type, nb_compressed - 8, nb_decompressed) })]
-    data: Vec<u8>,
-}
-
-fn parse_content_block<R: Read + Seek>(
-    reader: &mut R,
-    _block_type: &ContentBlockType,
-    nb_compressed: u64,
-    nb_decompressed: u64,
-) -> BinResult<Vec<u8>> {
-    match _block_type {
-        ContentBlockType::Zlib => {
-            let mut block = Vec::with_capacity(nb_decompressed as usize);
-            let mut decoder = ZlibDecoder::new(reader.take(nb_compressed));
-            decoder.read_to_end(&mut block)?;
-            Ok(block)
+    fn version(&self) -> Version {
+        if self.required_engine_version >= 3.0 {
+            Version::V3
+        } else if self.is_ver2() {
+            Version::V2
+        } else {
+            Version::V1
+        }
+    }
+
+    /// Encoding 为空时按 UTF-8 处理
+    fn encoding(&self) -> &'static Encoding {
+        encoding_for_label(&self.encoding).unwrap_or(UTF_8)
+    }
+
+    /// 由 KeyCaseSensitive 和 StripKey 得到 key 的规范化方式
+    pub fn normalizer(&self) -> KeyNormalizer {
+        KeyNormalizer {
+            case_sensitive: self.key_case_sensitive.eq_ignore_ascii_case("Yes"),
+            strip: self
+                .strip_key
+                .as_deref()
+                .is_some_and(|v| v.eq_ignore_ascii_case("Yes")),
+        }
+    }
+
+    /// record 是否经过压缩, 需要用 StyleSheet 展开其中的标记. 有的词典把 Compact 写作 Compat
+    pub fn is_compact(&self) -> bool {
+        [&self.compact, &self.compat]
+            .iter()
+            .any(|v| v.eq_ignore_ascii_case("Yes"))
+    }
+
+    pub fn styles(&self) -> StyleSheet {
+        StyleSheet::parse(&self.style_sheet)
+    }
+
+    /// bit 0: key block header 加密, bit 1: key block info 加密
+    fn encrypted(&self) -> u8 {
+        match self.encrypted.as_str() {
+            "No" | "" => 0,
+            "Yes" => 1,
+            v => v.parse().unwrap_or_default(),
+        }
+    }
+}
+
+/// 词典中 key 的排序和查询都以规范化之后的 key 为准
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub struct KeyNormalizer {
+    /// 为 `false` 时忽略大小写
+    pub case_sensitive: bool,
+    /// 为 `true` 时忽略空白和标点
+    pub strip: bool,
+}
+
+impl KeyNormalizer {
+    pub fn normalize<'a>(&self, key: &'a str) -> Cow<'a, str> {
+        let mut key = Cow::Borrowed(key);
+        if self.strip {
+            key = Cow::Owned(
+                key.chars()
+                    .filter(|c| !c.is_whitespace() && !c.is_ascii_punctuation())
+                    .collect(),
+            );
+        }
+        if !self.case_sensitive {
+            key = Cow::Owned(key.to_lowercase());
         }
-        ContentBlockType::UnCompressed => todo!(),
-        ContentBlockType::LZO => todo!(),
+
+        key
     }
 }
 
-#[derive(Debug, BinRead)]
-struct MdxKeyItem {
-    #[br(big)]
-    id: u64,
-    text: NullString,
+/// header 中 StyleSheet 定义的样式, 每个样式占三行: 编号, 开始和结束的 HTML
+#[derive(Debug, Clone, Default, PartialEq)]
+pub struct StyleSheet {
+    styles: HashMap<u32, (String, String)>,
 }
 
-fn parse_key_entries<R: Read + Seek>(reader: &mut R, info: &MdxKeyBlockInfo) -> BinResult<KeyMap> {
-    let mut map = KeyMap::new();
+impl StyleSheet {
+    /// 编号不是数字的样式会被忽略
+    pub fn parse(text: &str) -> Self {
+        let lines = text.lines().collect::<Vec<_>>();
+        let styles = lines
+            .chunks(3)
+            .filter_map(|v| match v {
+                [number, begin, end] => Some((
+                    number.trim().parse().ok()?,
+                    (begin.to_string(), end.to_string()),
+                )),
+                _ => None,
+            })
+            .collect();
+
+        StyleSheet { styles }
+    }
+
+    pub fn is_empty(&self) -> bool {
+        self.styles.is_empty()
+    }
 
-    for item in &info.data {
-        let block = MdxContentBlock::read_args(reader, (item.nb_compressed, item.nb_decompressed))?;
+    /// 编号为 `number` 的样式的开始和结束部分
+    pub fn get(&self, number: u32) -> Option<(&str, &str)> {
+        self.styles
+            .get(&number)
+            .map(|v| (v.0.as_str(), v.1.as_str()))
+    }
+
+    /// 把 record 中 `` `1` `` 这样的标记替换为样式, 标记之后到下一个标记为止的文本套用这个样式.
+    /// 未定义的标记原样保留
+    pub fn expand(&self, record: &str) -> String {
+        let mut text = String::with_capacity(record.len() * 2);
+        // 正在使用的样式的结束部分
+        let mut end = None;
+        let mut rest = record;
+
+        while let Some((before, style, after)) = self.marker(rest) {
+            styled(&mut text, before, end.take());
+            text.push_str(style.0);
+            end = Some(style.1);
+            rest = after;
+        }
+        styled(&mut text, rest, end);
 
-        {
-            let mut reader = Cursor::new(block.data);
+        text
+    }
 
-            for _ in 1..item.n_entries {
-                let kv = MdxKeyItem::read(&mut reader)?;
-                map.insert(kv.text.to_string(), kv.id);
+    /// 下一个已定义的标记之前的文本, 标记对应的样式和标记之后的文本
+    fn marker<'a>(&'a self, text: &'a str) -> Option<(&'a str, (&'a str, &'a str), &'a str)> {
+        let mut from = 0;
+        while let Some(i) = text[from..].find('`') {
+            let start = from + i + 1;
+            let end = text[start..]
+                .find(|c: char| !c.is_ascii_digit())
+                .map_or(text.len(), |v| start + v);
+
+            if end > start && text[end..].starts_with('`') {
+                if let Some(style) = text[start..end].parse().ok().and_then(|v| self.get(v)) {
+                    return Some((&text[..start - 1], style, &text[end + 1..]));
+                }
             }
+            from = start;
+        }
+
+        None
+    }
+}
+
+/// 以换行结尾的文本, 样式的结束部分放在换行之前
+fn styled(text: &mut String, segment: &str, end: Option<&str>) {
+    match end {
+        Some(end) if segment.ends_with('\n') => {
+            text.push_str(segment.trim_end());
+            text.push_str(end);
+            text.push_str("\r\n");
+        }
+        Some(end) => {
+            text.push_str(segment);
+            text.push_str(end);
         }
+        None => text.push_str(segment),
+    }
+}
+
+/// 大小写不敏感, 支持 `GB2312`, `UTF8`, `UTF-16LE` 这类别名
+fn encoding_for_label(label: &str) -> Option<&'static Encoding> {
+    match label.trim() {
+        "" => Some(UTF_8),
+        label => Encoding::for_label(label.as_bytes()).map(|v| {
+            // GB18030 兼容 GBK 和 GB2312
+            if v == GBK {
+                GB18030
+            } else {
+                v
+            }
+        }),
     }
+}
+
+fn decode(encoding: &'static Encoding, in_: &[u8]) -> Result<String> {
+    encoding
+        .decode_without_bom_handling_and_without_replacement(in_)
+        .map(|v| v.into_owned())
+        .ok_or_else(|| Error::Decode(encoding.name()))
+}
+
+/// 用户购买的注册码, `user_id` 为 header 中 RegisterBy 指定的 email 或 device id
+#[derive(Debug, Clone)]
+pub struct RegCode {
+    pub code: String,
+    pub user_id: String,
+}
 
-    Ok(map)
+/// content block 的 Adler-32 校验失败时的处理方式
+#[derive(Debug, Clone, Copy, PartialEq, Default)]
+pub enum Checksum {
+    #[default]
+    Strict,
+    /// 交给 [`ParseOptions::on_warning`], 继续使用这个 block
+    Warn,
 }
 
-#[derive(Debug, BinRead)]
-struct MdxRecordBlock {
-    #[br(big)]
-    n_blocks: u64,
-    #[br(big)]
-    n_entries: u64,
-    #[br(big)]
-    nb_info: u64,
-    #[br(big)]
-    nb_blocks: u64,
-    #[br(big, count(n_blocks))]
-    info: Vec<(u64, u64)>,
-    #[br(parse_with = |reader: &mut R, _: &ReadOptions, _: ()| -> BinResult<Vec<MdxContentBlock>> { parse_record_entries(reader, &info) })]
-    entries: Vec<MdxContentBlock>,
+/// 处理不影响继续使用词典的错误, 如 [`Checksum::Warn`] 时校验失败的 block, 以及没能保存的索引
+#[derive(Clone)]
+pub struct WarningHandler(Arc<dyn Fn(&Error) + Send + Sync>);
+
+impl WarningHandler {
+    pub fn new<F: Fn(&Error) + Send + Sync + 'static>(f: F) -> Self {
+        WarningHandler(Arc::new(f))
+    }
 }
 
-impl MdxRecordBlock {
-    fn record(&self, mut pos: u64) -> String {
-        std::str::from_utf8(
-            &self
-                .entries
+impl fmt::Debug for WarningHandler {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        f.write_str("WarningHandler")
+    }
+}
+
+#[derive(Debug, Clone, Default)]
+pub struct ParseOptions {
+    pub reg_code: Option<RegCode>,
+    pub checksum: Checksum,
+    /// 以 mmap 的方式打开文件, 打开时只解析 header 和 block 的索引,
+    /// key block 和 record block 在查询用到时才解压
+    pub lazy: bool,
+    /// 为 `None` 时忽略警告
+    pub on_warning: Option<WarningHandler>,
+}
+
+impl ParseOptions {
+    pub(crate) fn warn(&self, e: Error) {
+        if let Some(handler) = &self.on_warning {
+            (handler.0)(&e);
+        }
+    }
+}
+
+/// 词典文件的内容, 读入内存或者 mmap
+pub(crate) enum Source {
+    Bytes(Vec<u8>),
+    Mmap(Mmap),
+}
+
+impl Source {
+    pub(crate) fn open(path: &Path, lazy: bool) -> Result<Source> {
+        if !lazy {
+            return Ok(Source::Bytes(fs::read(path)?));
+        }
+
+        let file = File::open(path)?;
+        // 与所有 mmap 一样, 文件在使用期间不能被其他进程截断或修改
+        Ok(Source::Mmap(unsafe { Mmap::map(&file)? }))
+    }
+}
+
+impl Deref for Source {
+    type Target = [u8];
+
+    fn deref(&self) -> &[u8] {
+        match self {
+            Source::Bytes(v) => v,
+            Source::Mmap(v) => v,
+        }
+    }
+}
+
+impl fmt::Debug for Source {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self {
+            Source::Bytes(v) => write!(f, "Bytes({})", v.len()),
+            Source::Mmap(v) => write!(f, "Mmap({})", v.len()),
+        }
+    }
+}
+
+/// Salsa20/8 的核心函数, 4 轮 double round 之后加上输入
+fn salsa20_8_core(state: &[u32; 16]) -> [u32; 16] {
+    fn quarter_round(x: &mut [u32; 16], a: usize, b: usize, c: usize, d: usize) {
+        x[b] ^= x[a].wrapping_add(x[d]).rotate_left(7);
+        x[c] ^= x[b].wrapping_add(x[a]).rotate_left(9);
+        x[d] ^= x[c].wrapping_add(x[b]).rotate_left(13);
+        x[a] ^= x[d].wrapping_add(x[c]).rotate_left(18);
+    }
+
+    let mut x = *state;
+    for _ in 0..4 {
+        quarter_round(&mut x, 0, 4, 8, 12);
+        quarter_round(&mut x, 5, 9, 13, 1);
+        quarter_round(&mut x, 10, 14, 2, 6);
+        quarter_round(&mut x, 15, 3, 7, 11);
+        quarter_round(&mut x, 0, 1, 2, 3);
+        quarter_round(&mut x, 5, 6, 7, 4);
+        quarter_round(&mut x, 10, 11, 8, 9);
+        quarter_round(&mut x, 15, 12, 13, 14);
+    }
+    for (a, b) in x.iter_mut().zip(state) {
+        *a = a.wrapping_add(*b);
+    }
+
+    x
+}
+
+/// Salsa20/8, 128 位 key, nonce 为 0
+fn salsa20_8(in_: &[u8], key: &[u8]) -> Vec<u8> {
+    let word = |v: &[u8], i: usize| u32::from_le_bytes([v[i], v[i + 1], v[i + 2], v[i + 3]]);
+    let (k, tau) = (key, b"expand 16-byte k");
+
+    in_.chunks(64)
+        .enumerate()
+        .flat_map(|(n, chunk)| {
+            #[rustfmt::skip]
+            let state = [
+                word(tau, 0), word(k, 0), word(k, 4), word(k, 8),
+                word(k, 12), word(tau, 4), 0, 0,
+                n as u32, (n as u64 >> 32) as u32, word(tau, 8), word(k, 0),
+                word(k, 4), word(k, 8), word(k, 12), word(tau, 12),
+            ];
+
+            let stream = salsa20_8_core(&state)
                 .iter()
-                .find(|item| {
-                    let len = item.data.len() as u64;
-                    if pos > len {
-                        pos -= len;
-                        false
-                    } else {
-                        true
-                    }
-                })
-                .map(|item| {
-                    item.data
-                        .iter()
-                        .skip(pos as usize)
-                        .take_while(|c| **c != 0)
-                        .map(|c| *c)
-                        .collect::<Vec<u8>>()
-                })
-                .unwrap(),
-        )
-        .map(|v| v.to_string())
-        .unwrap_or_default()
-    }
-}
-
-#[derive(Debug, BinRead)]
-struct MdxRecordItem {
-    text: NullString,
-}
-
-fn parse_record_entries<R: Read + Seek>(
-    reader: &mut R,
-    info: &Vec<(u64, u64)>,
-) -> BinResult<Vec<MdxContentBlock>> {
-    info.iter()
-        .map(|item| MdxContentBlock::read_args(reader, *item))
-        .collect::<_>()
+                .flat_map(|v| v.to_le_bytes())
+                .collect::<Vec<u8>>();
+
+            chunk
+                .iter()
+                .zip(stream)
+                .map(|(a, b)| a ^ b)
+                .collect::<Vec<u8>>()
+        })
+        .collect()
+}
+
+fn ripemd128(in_: &[u8]) -> Vec<u8> {
+    let mut hasher = Ripemd128::new();
+    hasher.input(in_);
+    hasher.result().to_vec()
+}
+
+fn fast_decrypt(in_: &[u8], key: &[u8]) -> Vec<u8> {
+    let mut prev = 0x36;
+    in_.iter()
+        .enumerate()
+        .map(|(i, b)| {
+            let mut t = b.rotate_left(4);
+            t = t ^ prev ^ (i & 0xff) as u8 ^ key[i % key.len()];
+
+            prev = *b;
+            t
+        })
+        .collect()
+}
+
+/// 由注册码得到解密 key block header 的 key, 3.0 的词典则由 header 中的 UUID 得到
+fn encrypt_key(meta: &DictMeta, options: &ParseOptions) -> Result<Option<Vec<u8>>> {
+    if let (Version::V3, Some(uuid)) = (meta.version(), &meta.uuid) {
+        let (a, b) = uuid.as_bytes().split_at(uuid.len().div_ceil(2));
+        return Ok(Some(
+            [xxh64(a, 0).to_be_bytes(), xxh64(b, 0).to_be_bytes()].concat(),
+        ));
+    }
+
+    if meta.encrypted() & 1 == 0 {
+        return Ok(None);
+    }
+
+    let reg_code = options.reg_code.as_ref().ok_or(Error::Encrypted)?;
+
+    let code = reg_code.code.trim();
+    if code.len() != 32 || !code.is_ascii() {
+        return Err(Error::InvalidRegCode);
+    }
+    let code = (0..code.len())
+        .step_by(2)
+        .map(|i| u8::from_str_radix(&code[i..i + 2], 16))
+        .collect::<result::Result<Vec<u8>, _>>()
+        .map_err(|_| Error::InvalidRegCode)?;
+
+    let user_id = match meta.register_by.as_deref() {
+        Some(v) if v.eq_ignore_ascii_case("DeviceID") => reg_code.user_id.as_bytes().to_vec(),
+        _ => reg_code
+            .user_id
+            .encode_utf16()
+            .flat_map(|v| v.to_le_bytes())
+            .collect(),
+    };
+
+    Ok(Some(salsa20_8(&code, &ripemd128(&user_id))))
+}
+
+macro_rules! nom_return {
+    ($in_:tt, $output_t:ty, $x:expr) => {
+        match || -> Result<$output_t> { Ok($x) }() {
+            Ok(v) => Ok(($in_, v)),
+            Err(e) => Err(nom::Err::Error(e)),
+        }
+    };
+}
+
+pub fn cond_if<I, E, O, F1, F2>(
+    cond: bool,
+    mut f1: F1,
+    mut f2: F2,
+) -> impl FnMut(I) -> IResult<I, O, E>
+where
+    E: ParseError<I>,
+    F1: Parser<I, O, E>,
+    F2: Parser<I, O, E>,
+{
+    move |in_: I| {
+        if cond {
+            f1.parse(in_)
+        } else {
+            f2.parse(in_)
+        }
+    }
+}
+
+const HEADER: Location = Location {
+    section: Section::Header,
+    index: None,
+    offset: 0,
+};
+
+fn dict_meta(in_: &[u8]) -> NomResult<&[u8], DictMeta> {
+    let (in_, (data, checksum)) = tuple((length_data(be_u32), le_u32))(in_)?;
+
+    nom_return!(in_, DictMeta, {
+        if adler32_slice(data) != checksum {
+            return Err(Error::HeaderChecksum);
+        }
+
+        let dict_meta = data
+            .chunks_exact(2)
+            .map(|v| u16::from_le_bytes([v[0], v[1]]))
+            .collect::<Vec<u16>>();
+        let dict_meta = quick_xml::de::from_str::<DictMeta>(&String::from_utf16(&dict_meta)?)?;
+
+        if !(1.0..4.0).contains(&dict_meta.required_engine_version) {
+            return Err(Error::UnsupportedVersion(dict_meta.required_engine_version));
+        }
+
+        if encoding_for_label(&dict_meta.encoding).is_none() {
+            return Err(Error::UnknownEncoding(dict_meta.encoding));
+        }
+
+        dict_meta
+    })
+}
+
+pub mod mdd;
+pub(crate) mod mdx;
+
+#[cfg(test)]
+mod tests {
+    use super::{ripemd128, salsa20_8, salsa20_8_core, StyleSheet};
+
+    fn words(hex: &str) -> [u32; 16] {
+        let bytes = hex
+            .split_whitespace()
+            .flat_map(|v| (0..v.len()).step_by(2).map(move |i| &v[i..i + 2]))
+            .map(|v| u8::from_str_radix(v, 16).unwrap())
+            .collect::<Vec<_>>();
+        let mut words = [0; 16];
+        for (word, v) in words.iter_mut().zip(bytes.chunks(4)) {
+            *word = u32::from_le_bytes([v[0], v[1], v[2], v[3]]);
+        }
+        words
+    }
+
+    #[test]
+    fn salsa20_8_vector() {
+        // RFC 7914 第 8 节
+        let input = words(
+            "7e879a21 4f3ec986 7ca940e6 41718f26 baee555b 8c61c1b5 0df84611 6dcd3b1d
+             ee24f319 df9b3d85 14121e4b 5ac5aa32 76021d29 09c74829 edebc68d b8b8c25e",
+        );
+        let output = words(
+            "a41f859c 6608cc99 3b81cacb 020cef05 044b2181 a2fd337d fd7b1c63 96682f29
+             b4393168 e3c9e6bc fe6bc5b7 a06d96ba e424cc10 2c91745c 24ad673d c7618f81",
+        );
+        assert_eq!(salsa20_8_core(&input), output);
+    }
+
+    #[test]
+    fn salsa20_8_stream() {
+        let key = ripemd128(b"key");
+        let stream = salsa20_8(&[0; 128], &key);
+        // 每个 64 字节的 block 使用不同的计数器
+        assert_ne!(stream[64..], stream[..64]);
+        assert_eq!(salsa20_8(&stream, &key), [0; 128]);
+    }
+
+    fn styles() -> StyleSheet {
+        StyleSheet::parse("1\n<b>\n</b>\n2\n<i>\n</i>\nx\n<u>\n</u>\n3\n<s>\n")
+    }
+
+    #[test]
+    fn style_sheet_parse() {
+        let styles = styles();
+        assert_eq!(styles.get(1), Some(("<b>", "</b>")));
+        assert_eq!(styles.get(2), Some(("<i>", "</i>")));
+        // 编号不是数字和不完整的样式被忽略
+        assert_eq!(styles.get(3), None);
+        assert!(StyleSheet::parse("").is_empty());
+    }
+
+    #[test]
+    fn style_sheet_expand() {
+        let styles = styles();
+        assert_eq!(styles.expand("a`1`b`2`c"), "a<b>b</b><i>c</i>");
+        // 未定义的标记原样保留, 不结束正在使用的样式
+        assert_eq!(
+            styles.expand("`9`x `1`y `` `a` `3`z"),
+            "`9`x <b>y `` `a` `3`z</b>"
+        );
+        assert_eq!(styles.expand("plain"), "plain");
+    }
+
+    #[test]
+    fn style_sheet_line_end() {
+        let styles = styles();
+        // 结束部分放在换行之前, 换行统一为 `\r\n`
+        assert_eq!(
+            styles.expand("`1`word\n`2`next \r\n"),
+            "<b>word</b>\r\n<i>next</i>\r\n"
+        );
+        // 没有样式时保留原来的换行
+        assert_eq!(styles.expand("a\n`1`b"), "a\n<b>b</b>");
+    }
 }
@@ -0,0 +1,52 @@
+use std::path::Path;
+
+use super::{
+    mdx::{self, Mdx},
+    DictMeta, ParseOptions, Result, Source,
+};
+
+#[derive(Debug)]
+pub struct Mdd {
+    dict: Mdx,
+}
+
+impl Mdd {
+    pub fn open<P: AsRef<Path>>(path: P, options: &ParseOptions) -> Result<Self> {
+        Self::parse(Source::open(path.as_ref(), options.lazy)?, options)
+    }
+
+    pub fn from_bytes(bytes: &[u8], options: &ParseOptions) -> Result<Self> {
+        Self::parse(Source::Bytes(bytes.to_vec()), options)
+    }
+
+    fn parse(source: Source, options: &ParseOptions) -> Result<Self> {
+        // mdd 的 key 固定为 UTF-16, 与 header 中的 Encoding 无关
+        Ok(Mdd {
+            dict: mdx::parse_with(source, options, Some("UTF-16"))?,
+        })
+    }
+
+    pub fn dict_meta(&self) -> &DictMeta {
+        &self.dict.dict_meta
+    }
+
+    /// 所有资源的 key
+    pub fn keys(&self) -> impl Iterator<Item = Result<&str>> {
+        self.dict.keys().map(|v| v.map(|(_, _, key)| key))
+    }
+
+    /// 资源 key 形如 `\sound\foo.spx`, 也接受 `sound/foo.spx` 这样的写法
+    pub fn resource(&self, key: &str) -> Result<Option<Vec<u8>>> {
+        let key = key.replace('/', "\\");
+        let key = if key.starts_with('\\') {
+            key
+        } else {
+            format!("\\{}", key)
+        };
+
+        match self.dict.find(&key)?.first() {
+            Some((block, index, _)) => Ok(Some(self.dict.record(*block, *index)?.into_owned())),
+            None => Ok(None),
+        }
+    }
+}
@@ -0,0 +1,1139 @@
+use std::{
+    borrow::Cow,
+    io::{Cursor, Read},
+    ops::RangeFrom,
+    sync::OnceLock,
+};
+
+use byteorder::{LittleEndian, WriteBytesExt};
+
+use adler::adler32_slice;
+use encoding_rs::UTF_16LE;
+
+use super::{
+    cond_if, decode, dict_meta, encrypt_key, fast_decrypt, located, ripemd128, salsa20_8, Checksum,
+    DictMeta, Error, Location, NomResult, ParseOptions, Result, Section, Source, StyleSheet,
+    Version, HEADER,
+};
+use flate2::read::ZlibDecoder;
+use nom::{
+    bytes::streaming::{tag, take},
+    combinator::{cond, map, map_opt, map_parser, map_res},
+    error::{FromExternalError, ParseError},
+    multi::{count, length_count, length_data, many_till},
+    number::streaming::{be_u16, be_u32, be_u64, be_u8, le_u16, le_u32, le_u8},
+    sequence::tuple,
+    AsBytes, Compare, IResult, InputIter, InputLength, InputTake, Offset, Parser, Slice,
+};
+use xxhash_rust::xxh64::xxh64;
+
+/// 打开时只解析 key block 和 record block 的索引, block 在用到时才解压并缓存
+#[derive(Debug)]
+pub struct Mdx {
+    pub dict_meta: DictMeta,
+    source: Source,
+    // 3.0 的 block 解密用的 key
+    key: Option<Vec<u8>>,
+    options: ParseOptions,
+    key_blocks: Vec<KeyBlockInfo>,
+    key_cache: Vec<OnceLock<Vec<KeyEntry>>>,
+    record_blocks: Vec<RecordBlockInfo>,
+    record_cache: Vec<OnceLock<Vec<u8>>>,
+    // header 表示 record 经过压缩时用于展开 record 的样式
+    styles: Option<StyleSheet>,
+}
+
+/// record 的 offset 和 key
+pub(crate) type KeyEntry = (u64, String);
+
+impl Mdx {
+    fn new(source: Source, in_: usize, dict_meta: DictMeta, options: &ParseOptions) -> Result<Mdx> {
+        let key = encrypt_key(&dict_meta, options)?;
+
+        let (_, (key_blocks, record_blocks)) = match dict_meta.version() {
+            Version::V3 => {
+                v3_key_record(&source[in_..], &source, &dict_meta, key.as_deref(), options)
+            }
+            Version::V1 | Version::V2 => {
+                key_record(&source[in_..], &source, &dict_meta, key.as_deref(), options)
+            }
+        }?;
+
+        let styles = Some(dict_meta.styles()).filter(|v| dict_meta.is_compact() && !v.is_empty());
+
+        let mdx = Mdx {
+            dict_meta,
+            source,
+            key,
+            options: options.clone(),
+            key_cache: key_blocks.iter().map(|_| OnceLock::new()).collect(),
+            key_blocks,
+            record_cache: record_blocks.iter().map(|_| OnceLock::new()).collect(),
+            record_blocks,
+            styles,
+        };
+
+        if !options.lazy {
+            mdx.load()?;
+        }
+
+        Ok(mdx)
+    }
+
+    /// 解压所有的 block, 文件中的错误在此时全部暴露出来
+    fn load(&self) -> Result<()> {
+        for index in 0..self.key_blocks.len() {
+            self.key_block(index)?;
+        }
+        for index in 0..self.record_blocks.len() {
+            self.record_block(index)?;
+        }
+
+        Ok(())
+    }
+
+    fn block(
+        &self,
+        offset: usize,
+        nb_compressed: u64,
+        nb_decompressed: u64,
+        location: Location,
+    ) -> Result<Vec<u8>> {
+        let (_, data) = match self.dict_meta.version() {
+            Version::V3 => {
+                let end = offset.saturating_add(nb_compressed as usize + 8);
+                v3_content_block(
+                    self.source.get(offset..end).unwrap_or_default(),
+                    nb_decompressed as u32,
+                    location,
+                    self.key.as_deref(),
+                    &self.options,
+                )
+            }
+            Version::V1 | Version::V2 => content_block(
+                self.source.get(offset..).unwrap_or_default(),
+                nb_compressed,
+                nb_decompressed,
+                location,
+                &self.options,
+            ),
+        }?;
+
+        Ok(data)
+    }
+
+    /// 第 `index` 个 key block 中的所有 key
+    fn key_block(&self, index: usize) -> Result<&[KeyEntry]> {
+        let cache = &self.key_cache[index];
+        if let Some(entries) = cache.get() {
+            return Ok(entries);
+        }
+
+        let info = &self.key_blocks[index];
+        let location = Location {
+            section: Section::KeyBlock,
+            index: Some(index),
+            offset: info.offset,
+        };
+
+        let data = self.block(
+            info.offset,
+            info.nb_compressed,
+            info.nb_decompressed,
+            location,
+        )?;
+
+        // n_entries 没有校验, 不能直接用于分配
+        let mut entries = Vec::with_capacity((info.n_entries as usize).min(data.len()));
+        let mut in_ = data.as_bytes();
+        while !in_.is_empty() {
+            let (i_, entry) = located(
+                location,
+                tuple((mdx_number(&self.dict_meta), mdx_string(&self.dict_meta)))(in_),
+            )?;
+            in_ = i_;
+
+            entries.push(entry);
+        }
+
+        Ok(cache.get_or_init(|| entries))
+    }
+
+    fn record_block(&self, index: usize) -> Result<&[u8]> {
+        let cache = &self.record_cache[index];
+        if let Some(data) = cache.get() {
+            return Ok(data);
+        }
+
+        let info = &self.record_blocks[index];
+        let data = self.block(
+            info.offset,
+            info.nb_compressed,
+            info.nb_decompressed,
+            self.record_location(index),
+        )?;
+
+        Ok(cache.get_or_init(|| data))
+    }
+
+    /// 解压后位于 `start` 的数据所在的 record block, 超出范围时为最后一个
+    fn record_index(&self, start: u64) -> usize {
+        self.record_blocks
+            .partition_point(|v| v.start <= start)
+            .saturating_sub(1)
+    }
+
+    fn record_location(&self, index: usize) -> Location {
+        let info = self.record_blocks.get(index);
+        Location {
+            section: Section::RecordBlock,
+            index: info.map(|_| index),
+            offset: info.map_or(0, |v| v.offset),
+        }
+    }
+
+    /// 用于判断保存下来的索引是否属于这个词典. 只使用 header, key block 之前的部分
+    /// 和各个 record block 的大小, 不需要读取整个文件
+    pub(crate) fn fingerprint(&self) -> u64 {
+        let end = self.key_blocks.first().map_or(0, |v| v.offset);
+        let mut data = self.source[..end].to_vec();
+        for info in &self.record_blocks {
+            data.extend_from_slice(&info.nb_compressed.to_le_bytes());
+            data.extend_from_slice(&info.nb_decompressed.to_le_bytes());
+        }
+
+        xxh64(&data, self.source.len() as u64)
+    }
+
+    /// 按文件中的顺序遍历所有的 key, 同时返回 key 所在的 block 和位置
+    pub(crate) fn keys(&self) -> impl Iterator<Item = Result<(usize, usize, &str)>> {
+        self.blocks_from(0)
+    }
+
+    /// 从第一个规范化之后不小于 `key` 的 key 开始按顺序遍历, 只解压需要的 block
+    pub(crate) fn keys_from<'a>(
+        &'a self,
+        key: &str,
+    ) -> impl Iterator<Item = Result<(usize, usize, &'a str)>> {
+        let normalizer = self.dict_meta.normalizer();
+        let key = normalizer.normalize(key).into_owned();
+
+        let (start, error) = match self.first_block(&key) {
+            Ok(block) => (block, None),
+            Err(e) => (self.key_blocks.len(), Some(Err(e))),
+        };
+
+        error.into_iter().chain(
+            self.blocks_from(start)
+                .skip_while(move |v| matches!(v, Ok((_, _, k)) if *normalizer.normalize(k) < *key)),
+        )
+    }
+
+    fn blocks_from(&self, start: usize) -> impl Iterator<Item = Result<(usize, usize, &str)>> {
+        (start..self.key_blocks.len()).flat_map(move |block| {
+            let (entries, error) = match self.key_block(block) {
+                Ok(entries) => (entries, None),
+                Err(e) => (&[][..], Some(Err(e))),
+            };
+
+            entries
+                .iter()
+                .enumerate()
+                .map(move |(index, entry)| Ok((block, index, entry.1.as_str())))
+                .chain(error)
+        })
+    }
+
+    pub(crate) fn key(&self, block: usize, index: usize) -> Result<&str> {
+        Ok(&self.key_block(block)?[index].1)
+    }
+
+    /// key block 的第一个和最后一个 key, 没有 key index 的 3.0 词典需要解压 block 才能得到
+    fn bounds(&self, block: usize) -> Result<(&str, &str)> {
+        let info = &self.key_blocks[block];
+        if self.dict_meta.version() != Version::V3 || !info.tail.is_empty() {
+            return Ok((&info.head, &info.tail));
+        }
+
+        let entries = self.key_block(block)?;
+        Ok(match (entries.first(), entries.last()) {
+            (Some(head), Some(tail)) => (&head.1, &tail.1),
+            _ => ("", ""),
+        })
+    }
+
+    /// 第一个 tail 不小于 `key` 的 block, `key` 为规范化之后的 key
+    fn first_block(&self, key: &str) -> Result<usize> {
+        let normalizer = self.dict_meta.normalizer();
+
+        let (mut lo, mut hi) = (0, self.key_blocks.len());
+        while lo < hi {
+            let mid = lo + (hi - lo) / 2;
+            if *normalizer.normalize(self.bounds(mid)?.1) < *key {
+                lo = mid + 1;
+            } else {
+                hi = mid;
+            }
+        }
+
+        Ok(lo)
+    }
+
+    /// 规范化之后以 `prefix` 开头的 key, 按词典中的顺序排列, 相邻的相同 key 只保留一个
+    pub(crate) fn complete(&self, prefix: &str, limit: usize) -> Result<Vec<&str>> {
+        let normalizer = self.dict_meta.normalizer();
+        let prefix = normalizer.normalize(prefix);
+
+        let mut found: Vec<&str> = Vec::new();
+
+        for block in self.first_block(&prefix)?..self.key_blocks.len() {
+            let head = normalizer.normalize(self.bounds(block)?.0);
+            if head > prefix && !head.starts_with(&*prefix) {
+                break;
+            }
+
+            let entries = self.key_block(block)?;
+            let start = entries.partition_point(|v| normalizer.normalize(&v.1) < prefix);
+            for entry in &entries[start..] {
+                if found.len() >= limit || !normalizer.normalize(&entry.1).starts_with(&*prefix) {
+                    return Ok(found);
+                }
+                if found.last() != Some(&entry.1.as_str()) {
+                    found.push(&entry.1);
+                }
+            }
+        }
+
+        Ok(found)
+    }
+
+    /// 先按 head 和 tail 二分查找 key 所在的 block, 只解压这个 block, 再在 block 中二分查找,
+    /// 返回所有规范化之后与 `key` 相同的 key 以及它们所在的 block 和位置
+    pub(crate) fn find(&self, key: &str) -> Result<Vec<(usize, usize, &str)>> {
+        let normalizer = self.dict_meta.normalizer();
+        let key = normalizer.normalize(key);
+
+        let mut found = Vec::new();
+
+        // 相同的 key 可能跨越多个 block
+        for block in self.first_block(&key)?..self.key_blocks.len() {
+            if normalizer.normalize(self.bounds(block)?.0) > key {
+                break;
+            }
+
+            let entries = self.key_block(block)?;
+            let start = entries.partition_point(|v| normalizer.normalize(&v.1) < key);
+            for (index, entry) in entries.iter().enumerate().skip(start) {
+                if normalizer.normalize(&entry.1) != key {
+                    break;
+                }
+                found.push((block, index, entry.1.as_str()));
+            }
+        }
+
+        Ok(found)
+    }
+
+    /// record 的结尾为下一个 key 的 offset, 最后一个 key 的 record 到 record 数据的结尾为止
+    pub(crate) fn record(&self, block: usize, index: usize) -> Result<Cow<'_, [u8]>> {
+        let entries = self.key_block(block)?;
+        let start = entries[index].0;
+        let next = match entries.get(index + 1) {
+            Some(v) => Some(v.0),
+            None if block + 1 < self.key_blocks.len() => {
+                self.key_block(block + 1)?.first().map(|v| v.0)
+            }
+            None => None,
+        };
+
+        self.record_data(start, next)
+    }
+
+    /// 解码后的 record, 去掉结尾的 `\0`, 压缩的 record 展开其中的样式标记
+    pub(crate) fn text(&self, block: usize, index: usize) -> Result<String> {
+        let data = self.record(block, index)?;
+        let start = self.key_block(block)?[index].0;
+        let text = decode(self.dict_meta.encoding(), &data)
+            .map_err(|e| e.at(self.record_location(self.record_index(start))))?;
+        let text = text.trim_end_matches('\0');
+
+        Ok(match &self.styles {
+            Some(styles) => styles.expand(text),
+            None => text.to_string(),
+        })
+    }
+
+    /// `end` 为 `None` 时到 record 数据的结尾为止, 不在 `start` 之后时以 `start` 所在
+    /// record block 的结尾为准
+    fn record_data(&self, start: u64, end: Option<u64>) -> Result<Cow<'_, [u8]>> {
+        let index = self.record_index(start);
+        let info = self
+            .record_blocks
+            .get(index)
+            .filter(|v| start < v.end())
+            .ok_or_else(|| Error::InvalidOffset(start).at(self.record_location(index)))?;
+        let end = match end {
+            Some(end) if end > start => end,
+            Some(_) => info.end(),
+            None => self.record_blocks.last().map_or(0, RecordBlockInfo::end),
+        };
+
+        let data = self.record_block(index)?;
+        let from = (start - info.start) as usize;
+
+        if end <= info.end() {
+            return data
+                .get(from..(end - info.start) as usize)
+                .map(Cow::Borrowed)
+                .ok_or_else(|| Error::InvalidOffset(start).at(self.record_location(index)));
+        }
+
+        // record 跨越了多个 record block
+        let mut output = data.get(from..).unwrap_or_default().to_vec();
+        for (index, info) in self.record_blocks.iter().enumerate().skip(index + 1) {
+            if info.start >= end {
+                break;
+            }
+
+            let data = self.record_block(index)?;
+            output.extend_from_slice(&data[..((end - info.start) as usize).min(data.len())]);
+        }
+
+        Ok(Cow::Owned(output))
+    }
+}
+
+#[derive(Debug)]
+struct KeyBlockHeader {
+    n_blocks: u64,
+    #[allow(dead_code)]
+    n_entries: u64,
+    #[allow(dead_code)]
+    nb_decompressed: Option<u64>,
+    nb_block_info: u64,
+    nb_blocks: u64,
+    checksum: Option<u32>,
+}
+
+fn mdx_number<I, E>(meta: &DictMeta) -> impl FnMut(I) -> IResult<I, u64, E>
+where
+    I: Slice<RangeFrom<usize>> + InputIter<Item = u8> + InputLength,
+    E: ParseError<I>,
+{
+    cond_if(meta.is_ver2(), be_u64, map(be_u32, |v| v as u64))
+}
+
+const U8NULL: &[u8] = &[0u8];
+const U16NULL: &[u8] = &[0u8, 0u8];
+
+fn utf16_bytes(in_: &[u16]) -> Vec<u8> {
+    in_.iter().flat_map(|v| v.to_le_bytes()).collect()
+}
+
+fn mdx_string<I, E>(meta: &DictMeta) -> impl FnMut(I) -> IResult<I, String, E>
+where
+    I: Clone
+        + PartialEq
+        + Slice<RangeFrom<usize>>
+        + InputIter<Item = u8>
+        + InputLength
+        + InputTake
+        + Compare<&'static [u8]>,
+    E: ParseError<I> + FromExternalError<I, Error>,
+{
+    let encoding = meta.encoding();
+
+    map_res(
+        cond_if(
+            encoding != UTF_16LE,
+            map(many_till(le_u8, tag(U8NULL)), |(v, _)| v),
+            map(many_till(le_u16, tag(U16NULL)), |(v, _)| utf16_bytes(&v)),
+        ),
+        move |v| decode(encoding, &v),
+    )
+}
+
+fn key_block<'a>(
+    in_: &'a [u8],
+    file: &[u8],
+    meta: &DictMeta,
+    key: Option<&[u8]>,
+    options: &ParseOptions,
+) -> NomResult<&'a [u8], Vec<KeyBlockInfo>> {
+    let location = Location {
+        section: Section::KeyInfo,
+        index: None,
+        offset: file.offset(in_),
+    };
+
+    let (in_, data) = located(
+        location,
+        take(if meta.is_ver2() { 40u8 } else { 16u8 })(in_),
+    )?;
+    let (in_, checksum) = located(location, cond(meta.is_ver2(), be_u32)(in_))?;
+
+    let data = match key {
+        Some(key) => salsa20_8(data, key),
+        None => data.to_vec(),
+    };
+
+    let (_, header) = located(
+        location,
+        map(
+            tuple((
+                mdx_number(meta),
+                mdx_number(meta),
+                cond(meta.is_ver2(), be_u64),
+                mdx_number(meta),
+                mdx_number(meta),
+            )),
+            |(n_blocks, n_entries, nb_decompressed, nb_block_info, nb_blocks)| KeyBlockHeader {
+                n_entries,
+                n_blocks,
+                nb_decompressed,
+                nb_block_info,
+                nb_blocks,
+                checksum,
+            },
+        )(data.as_bytes()),
+    )?;
+
+    if header.checksum.is_some_and(|v| v != adler32_slice(&data)) {
+        return Err(nom::Err::Failure(Error::KeyHeaderChecksum.at(location)));
+    }
+
+    let location = Location {
+        offset: file.offset(in_),
+        ..location
+    };
+    let (in_, mut infos) = located(
+        location,
+        key_block_info(in_, &header, meta, location, options),
+    )?;
+
+    let mut offset = file.offset(in_);
+    let (in_, _) = located(
+        Location {
+            section: Section::KeyBlock,
+            index: None,
+            offset,
+        },
+        take(header.nb_blocks)(in_),
+    )?;
+
+    check_sizes(
+        "key block size",
+        infos.iter().map(|v| (v.nb_compressed, 0)),
+        header.nb_blocks,
+    )
+    .map_err(|e| nom::Err::Failure(e.at(location)))?;
+
+    for info in infos.iter_mut() {
+        info.offset = offset;
+        offset += info.nb_compressed as usize + 8;
+    }
+
+    Ok((in_, infos))
+}
+
+#[derive(Debug)]
+struct KeyBlockInfo {
+    n_entries: u64,
+    head: String,
+    tail: String,
+    nb_compressed: u64,
+    nb_decompressed: u64,
+    // block 在文件中的偏移
+    offset: usize,
+}
+
+/// 各个 key block 的 key 的数量, 第一个和最后一个 key 以及大小. 3.0 的 key index 也是这样的格式
+fn key_block_infos<'a>(
+    in_: &'a [u8],
+    n_blocks: u64,
+    meta: &DictMeta,
+) -> NomResult<&'a [u8], Vec<KeyBlockInfo>> {
+    fn info_key<I, E>(meta: &DictMeta) -> impl FnMut(I) -> IResult<I, String, E>
+    where
+        I: Clone + Slice<RangeFrom<usize>> + InputIter<Item = u8> + InputLength,
+        E: ParseError<I> + FromExternalError<I, Error>,
+    {
+        let is_ver2 = meta.is_ver2();
+        let encoding = meta.encoding();
+
+        fn key_bytes<I, O, E, F>(is_ver2: bool, f: F) -> impl Parser<I, Vec<O>, E>
+        where
+            I: Clone + Slice<RangeFrom<usize>> + InputIter<Item = u8> + InputLength,
+            F: Parser<I, O, E>,
+            E: ParseError<I>,
+        {
+            map(
+                length_count(
+                    map(
+                        cond_if(is_ver2, be_u16, map(be_u8, |v| v as u16)),
+                        move |v| {
+                            if is_ver2 {
+                                v as usize + 1
+                            } else {
+                                v as usize
+                            }
+                        },
+                    ),
+                    f,
+                ),
+                move |mut v| {
+                    if is_ver2 {
+                        v.truncate(v.len() - 1);
+                    }
+                    v
+                },
+            )
+        }
+
+        map_res(
+            cond_if(
+                encoding != UTF_16LE,
+                key_bytes(is_ver2, le_u8),
+                map(key_bytes(is_ver2, le_u16), |v| utf16_bytes(&v)),
+            ),
+            move |v| decode(encoding, &v),
+        )
+    }
+
+    // 每个 block 的信息至少包含三个数字, 数量超过剩下的数据时文件已经损坏
+    let width = if meta.is_ver2() { 8 } else { 4 };
+    check_count("key block count", n_blocks, 3 * width, in_.len()).map_err(nom::Err::Failure)?;
+
+    let (in_, infos) = count(
+        map_opt(
+            tuple((
+                mdx_number(meta),
+                info_key(meta),
+                info_key(meta),
+                mdx_number(meta),
+                mdx_number(meta),
+            )),
+            |(n_entries, head, tail, nb_compressed, nb_decompressed): (
+                u64,
+                String,
+                String,
+                u64,
+                u64,
+            )| {
+                Some(KeyBlockInfo {
+                    n_entries,
+                    head,
+                    tail,
+                    // 不包含 type 和 checksum
+                    nb_compressed: nb_compressed.checked_sub(8)?,
+                    nb_decompressed,
+                    offset: 0,
+                })
+            },
+        ),
+        n_blocks as usize,
+    )(in_)?;
+
+    Ok((in_, infos))
+}
+
+fn key_block_info<'a>(
+    in_: &'a [u8],
+    header: &KeyBlockHeader,
+    meta: &DictMeta,
+    location: Location,
+    options: &ParseOptions,
+) -> NomResult<&'a [u8], Vec<KeyBlockInfo>> {
+    fn unzip(in_: &[u8], checksum: u32, encrypted: bool) -> NomResult<&[u8], Vec<u8>> {
+        nom_return!(in_, Vec<u8>, {
+            let in_ = if encrypted {
+                let mut vec = Vec::with_capacity(8);
+                vec.write_u32::<LittleEndian>(checksum)?;
+                vec.write_u32::<LittleEndian>(0x3695)?;
+
+                fast_decrypt(in_, &ripemd128(&vec))
+            } else {
+                in_.to_vec()
+            };
+
+            let mut output = Vec::new();
+
+            {
+                let mut decoder = ZlibDecoder::new(Cursor::new(in_));
+                decoder.read_to_end(&mut output)?;
+            }
+
+            output
+        })
+    }
+
+    let (in_, infos) = if meta.is_ver2() {
+        let (in_, (_, checksum, data)) = tuple((
+            le_u32,
+            le_u32,
+            // 不包含 type 和 checksum
+            take(header.nb_block_info.saturating_sub(8)),
+        ))(in_)?;
+
+        let (_, input) = unzip(data, checksum, meta.encrypted() & 2 != 0)?;
+        // checksum 为解压后的数据的 Adler-32, 按大端保存
+        verify_checksum(checksum.swap_bytes(), &input, location, options)
+            .map_err(nom::Err::Failure)?;
+
+        let (_, infos) = key_block_infos(&input, header.n_blocks, meta)?;
+        (in_, infos)
+    } else {
+        key_block_infos(in_, header.n_blocks, meta)?
+    };
+
+    Ok((in_, infos))
+}
+
+#[derive(Debug)]
+enum ContentBlockType {
+    UnCompressed = 0,
+    Lzo = 1,
+    Zlib = 2,
+}
+
+#[derive(Debug)]
+struct ContentBlock<'a> {
+    block_type: ContentBlockType,
+    checksum: u32,
+    data: &'a [u8],
+}
+
+/// 出错时的位置为 `location`
+fn content_block<'a>(
+    in_: &'a [u8],
+    nb_compressed: u64,
+    nb_decompressed: u64,
+    location: Location,
+    options: &ParseOptions,
+) -> NomResult<&'a [u8], Vec<u8>> {
+    let (in_, block) = located(
+        location,
+        map(
+            tuple((map_res(le_u32, block_type), be_u32, take(nb_compressed))),
+            |(block_type, checksum, data)| ContentBlock {
+                block_type,
+                checksum,
+                data,
+            },
+        )(in_),
+    )?;
+
+    located(
+        location,
+        nom_return!(in_, Vec<u8>, {
+            let output = decompress(block.block_type, block.data, nb_decompressed)?;
+            verify_checksum(block.checksum, &output, location, options)?;
+            output
+        }),
+    )
+}
+
+fn block_type(v: u32) -> Result<ContentBlockType> {
+    match v {
+        0 => Ok(ContentBlockType::UnCompressed),
+        1 => Ok(ContentBlockType::Lzo),
+        2 => Ok(ContentBlockType::Zlib),
+        _ => Err(Error::UnknownBlockType(v)),
+    }
+}
+
+/// 各个 block 压缩后的大小 (不包含 type 和 checksum) 加上 8 个字节的总和应当等于 `nb_blocks`,
+/// 解压后的大小的总和不能溢出
+fn check_sizes(
+    what: &'static str,
+    sizes: impl Iterator<Item = (u64, u64)>,
+    nb_blocks: u64,
+) -> Result<()> {
+    let mut compressed = 0u64;
+    let mut decompressed = 0u64;
+    for (nb_compressed, nb_decompressed) in sizes {
+        compressed = compressed
+            .checked_add(nb_compressed)
+            .and_then(|v| v.checked_add(8))
+            .ok_or(Error::InvalidSize(what, nb_compressed))?;
+        decompressed = decompressed
+            .checked_add(nb_decompressed)
+            .ok_or(Error::InvalidSize(what, nb_decompressed))?;
+    }
+
+    if compressed != nb_blocks {
+        return Err(Error::InvalidSize(what, compressed));
+    }
+    Ok(())
+}
+
+/// zlib 的压缩率最高约为 1032:1, LZO 更低, 超过这个比例的解压后大小只能来自损坏的文件
+const MAX_RATIO: u64 = 1032;
+
+/// `n` 个至少 `size` 字节的项目是否能放进 `available` 字节中. 数量来自文件中没有校验的部分,
+/// 直接交给 `count` 会按它预先分配内存
+fn check_count(what: &'static str, n: u64, size: usize, available: usize) -> Result<()> {
+    if n.saturating_mul(size as u64) > available as u64 {
+        return Err(Error::InvalidSize(what, n));
+    }
+    Ok(())
+}
+
+fn decompress(block_type: ContentBlockType, data: &[u8], nb_decompressed: u64) -> Result<Vec<u8>> {
+    if nb_decompressed > (data.len() as u64 + 64) * MAX_RATIO {
+        return Err(Error::InvalidSize("decompressed size", nb_decompressed));
+    }
+
+    Ok(match block_type {
+        ContentBlockType::Zlib => {
+            let mut output = Vec::with_capacity(nb_decompressed as usize);
+            let mut decoder = ZlibDecoder::new(Cursor::new(data));
+            decoder.read_to_end(&mut output)?;
+            output
+        }
+        ContentBlockType::UnCompressed => data.to_vec(),
+        ContentBlockType::Lzo => {
+            let lzo = minilzo_rs::LZO::init()?;
+
+            lzo.decompress(data, nb_decompressed as usize)?
+        }
+    })
+}
+
+fn verify_checksum(
+    checksum: u32,
+    data: &[u8],
+    location: Location,
+    options: &ParseOptions,
+) -> Result<()> {
+    if adler32_slice(data) != checksum {
+        match options.checksum {
+            Checksum::Strict => return Err(Error::Checksum),
+            Checksum::Warn => options.warn(Error::Checksum.at(location)),
+        }
+    }
+
+    Ok(())
+}
+
+#[derive(Debug)]
+struct RecordBlockHeader {
+    n_blocks: u64,
+    #[allow(dead_code)]
+    n_entries: u64,
+    nb_block_info: u64,
+    nb_blocks: u64,
+}
+
+#[derive(Debug)]
+struct RecordBlockInfo {
+    nb_compressed: u64,
+    nb_decompressed: u64,
+    // block 在文件中的偏移
+    offset: usize,
+    // 解压后在所有 record 中的起始位置
+    start: u64,
+}
+
+impl RecordBlockInfo {
+    fn end(&self) -> u64 {
+        self.start + self.nb_decompressed
+    }
+}
+
+fn record_block<'a>(
+    in_: &'a [u8],
+    file: &[u8],
+    meta: &DictMeta,
+) -> NomResult<&'a [u8], Vec<RecordBlockInfo>> {
+    let location = Location {
+        section: Section::RecordBlock,
+        index: None,
+        offset: file.offset(in_),
+    };
+
+    let (in_, header) = located(
+        location,
+        map(
+            tuple((
+                mdx_number(meta),
+                mdx_number(meta),
+                mdx_number(meta),
+                mdx_number(meta),
+            )),
+            |(n_blocks, n_entries, nb_block_info, nb_blocks)| RecordBlockHeader {
+                n_blocks,
+                n_entries,
+                nb_block_info,
+                nb_blocks,
+            },
+        )(in_),
+    )?;
+
+    // record block 的信息为两个数字
+    let width = if meta.is_ver2() { 8 } else { 4 };
+    check_count(
+        "record block count",
+        header.n_blocks,
+        2 * width,
+        header.nb_block_info.min(in_.len() as u64) as usize,
+    )
+    .map_err(|e| nom::Err::Failure(e.at(location)))?;
+
+    let (in_, mut infos) = located(
+        location,
+        map_parser(
+            take(header.nb_block_info),
+            count(
+                map_opt(
+                    tuple((mdx_number(meta), mdx_number(meta))),
+                    |(nb_compressed, nb_decompressed)| {
+                        Some(RecordBlockInfo {
+                            // 不包含 type 和 checksum
+                            nb_compressed: nb_compressed.checked_sub(8)?,
+                            nb_decompressed,
+                            offset: 0,
+                            start: 0,
+                        })
+                    },
+                ),
+                header.n_blocks as usize,
+            ),
+        )(in_),
+    )?;
+
+    let mut offset = file.offset(in_);
+    let (in_, _) = located(location, take(header.nb_blocks)(in_))?;
+
+    check_sizes(
+        "record block size",
+        infos.iter().map(|v| (v.nb_compressed, v.nb_decompressed)),
+        header.nb_blocks,
+    )
+    .map_err(|e| nom::Err::Failure(e.at(location)))?;
+
+    // 大小的总和已经检查过, 不会溢出
+    let mut start = 0;
+    for info in infos.iter_mut() {
+        info.offset = offset;
+        info.start = start;
+        offset += info.nb_compressed as usize + 8;
+        start += info.nb_decompressed;
+    }
+
+    Ok((in_, infos))
+}
+
+const V3_RECORD_DATA: u32 = 0x0100_0000;
+const V3_KEY_DATA: u32 = 0x0300_0000;
+const V3_KEY_INDEX: u32 = 0x0400_0000;
+
+/// MDict 3.0 在 header 之后是若干 section, 每个 section 以 type 和长度开头.
+/// 这里需要 key data, record data 和 key index, record index 直接跳过
+fn v3_key_record<'a>(
+    mut in_: &'a [u8],
+    file: &[u8],
+    meta: &DictMeta,
+    key: Option<&[u8]>,
+    options: &ParseOptions,
+) -> NomResult<&'a [u8], (Vec<KeyBlockInfo>, Vec<RecordBlockInfo>)> {
+    let mut key_data = None;
+    let mut record_data = None;
+    let mut key_index = None;
+
+    while !in_.is_empty() {
+        let location = Location {
+            section: Section::KeyInfo,
+            index: None,
+            offset: file.offset(in_),
+        };
+
+        let (i_, (section_type, data)) =
+            located(location, tuple((be_u32, length_data(be_u64)))(in_))?;
+        in_ = i_;
+
+        match section_type {
+            V3_KEY_DATA => key_data = Some(data),
+            V3_RECORD_DATA => record_data = Some(data),
+            V3_KEY_INDEX => key_index = Some(data),
+            _ => {}
+        }
+    }
+
+    // 缺少 key 或 record 的 section 时视为文件被截断
+    let missing = |section| Location {
+        section,
+        index: None,
+        offset: file.len(),
+    };
+    let key_data = key_data
+        .ok_or_else(|| nom::Err::Failure(Error::Truncated.at(missing(Section::KeyBlock))))?;
+    let record_data = record_data
+        .ok_or_else(|| nom::Err::Failure(Error::Truncated.at(missing(Section::RecordBlock))))?;
+
+    fn v3_blocks<'a>(
+        in_: &'a [u8],
+        file: &[u8],
+        section: Section,
+    ) -> NomResult<&'a [u8], Vec<(u32, &'a [u8])>> {
+        let location = Location {
+            section,
+            index: None,
+            offset: file.offset(in_),
+        };
+
+        let (in_, (n_blocks, _nb_blocks)) = located(location, tuple((be_u32, be_u64))(in_))?;
+        // 每个 block 至少有 8 个字节的大小
+        check_count("block count", n_blocks as u64, 8, in_.len())
+            .map_err(|e| nom::Err::Failure(e.at(location)))?;
+        located(
+            location,
+            count(tuple((be_u32, length_data(be_u32))), n_blocks as usize)(in_),
+        )
+    }
+
+    // 3.0 的 block 的大小包含头部的 8 个字节
+    let (_, blocks) = v3_blocks(key_data, file, Section::KeyBlock)?;
+    let mut key_blocks: Vec<_> = blocks
+        .into_iter()
+        .map(|(nb_decompressed, block)| KeyBlockInfo {
+            n_entries: 0,
+            head: String::new(),
+            tail: String::new(),
+            nb_compressed: block.len().saturating_sub(8) as u64,
+            nb_decompressed: nb_decompressed as u64,
+            offset: file.offset(block),
+        })
+        .collect();
+
+    // key index 只用于查找时跳过不需要的 key block, 无法使用时与没有 key index 一样
+    let infos = key_index.and_then(|v| {
+        let (_, blocks) = v3_blocks(v, file, Section::KeyInfo).ok()?;
+        let location = Location {
+            section: Section::KeyInfo,
+            index: None,
+            offset: file.offset(v),
+        };
+        v3_key_index(&blocks, key_blocks.len(), location, meta, key, options).ok()
+    });
+    if let Some(infos) = infos {
+        for (block, info) in key_blocks.iter_mut().zip(infos) {
+            block.n_entries = info.n_entries;
+            block.head = info.head;
+            block.tail = info.tail;
+        }
+    }
+
+    let (_, blocks) = v3_blocks(record_data, file, Section::RecordBlock)?;
+    let mut start = 0;
+    let record_blocks = blocks
+        .into_iter()
+        .map(|(nb_decompressed, block)| {
+            let info = RecordBlockInfo {
+                nb_compressed: block.len().saturating_sub(8) as u64,
+                nb_decompressed: nb_decompressed as u64,
+                offset: file.offset(block),
+                start,
+            };
+            start += nb_decompressed as u64;
+            info
+        })
+        .collect();
+
+    Ok((in_, (key_blocks, record_blocks)))
+}
+
+/// 3.0 的 key index 由若干 block 组成, 解压之后连在一起, 格式与 2.0 的 key block info 相同
+fn v3_key_index(
+    blocks: &[(u32, &[u8])],
+    n_blocks: usize,
+    location: Location,
+    meta: &DictMeta,
+    key: Option<&[u8]>,
+    options: &ParseOptions,
+) -> Result<Vec<KeyBlockInfo>> {
+    let mut data = Vec::new();
+    for (nb_decompressed, block) in blocks {
+        let (_, block) = v3_content_block(block, *nb_decompressed, location, key, options)?;
+        data.extend_from_slice(&block);
+    }
+
+    let (_, infos) = located(location, key_block_infos(&data, n_blocks as u64, meta))?;
+    Ok(infos)
+}
+
+/// 3.0 的 block 头部: 低 4 位为压缩方式, 4-8 位为加密方式, 8-16 位为加密的长度,
+/// checksum 针对解密后 (解压前) 的数据
+fn v3_content_block<'a>(
+    in_: &'a [u8],
+    nb_decompressed: u32,
+    location: Location,
+    key: Option<&[u8]>,
+    options: &ParseOptions,
+) -> NomResult<&'a [u8], Vec<u8>> {
+    let (data, (info, checksum)) = located(location, tuple((le_u32, take(4u8)))(in_))?;
+
+    let in_ = &data[data.len()..];
+
+    located(
+        location,
+        nom_return!(in_, Vec<u8>, {
+            let key = key.map_or_else(|| ripemd128(checksum), |v| v.to_vec());
+            let n_encrypted = ((info >> 8 & 0xff) as usize).min(data.len());
+            let (encrypted, rest) = data.split_at(n_encrypted);
+
+            let mut data = match info >> 4 & 0xf {
+                0 => encrypted.to_vec(),
+                1 => fast_decrypt(encrypted, &key),
+                2 => salsa20_8(encrypted, &key),
+                v => return Err(Error::UnknownEncryption(v)),
+            };
+            data.extend_from_slice(rest);
+
+            let checksum = u32::from_be_bytes([checksum[0], checksum[1], checksum[2], checksum[3]]);
+            verify_checksum(checksum, &data, location, options)?;
+
+            decompress(block_type(info & 0xf)?, &data, nb_decompressed as u64)?
+        }),
+    )
+}
+
+fn key_record<'a>(
+    in_: &'a [u8],
+    file: &[u8],
+    meta: &DictMeta,
+    key: Option<&[u8]>,
+    options: &ParseOptions,
+) -> NomResult<&'a [u8], (Vec<KeyBlockInfo>, Vec<RecordBlockInfo>)> {
+    let (in_, key_blocks) = key_block(in_, file, meta, key, options)?;
+    let (in_, record_blocks) = record_block(in_, file, meta)?;
+
+    Ok((in_, (key_blocks, record_blocks)))
+}
+
+/// `encoding` 覆盖 header 中的 Encoding
+pub(super) fn parse_with(
+    source: Source,
+    options: &ParseOptions,
+    encoding: Option<&str>,
+) -> Result<Mdx> {
+    let (in_, mut dict_meta) = located(HEADER, dict_meta(&source))?;
+    let in_ = source.offset(in_);
+
+    if let Some(encoding) = encoding {
+        dict_meta.encoding = encoding.to_string();
+    }
+
+    Mdx::new(source, in_, dict_meta, options)
+}
+
+pub(crate) fn parse(source: Source, options: &ParseOptions) -> Result<Mdx> {
+    let (_, dict_meta) = located(HEADER, dict_meta(&source))?;
+    // 3.0 的 key 和 record 固定为 UTF-8
+    let encoding = match dict_meta.version() {
+        Version::V3 => Some("UTF-8"),
+        Version::V1 | Version::V2 => None,
+    };
+
+    parse_with(source, options, encoding)
+}
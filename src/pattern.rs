@@ -0,0 +1,68 @@
+use regex::{Regex, RegexBuilder};
+
+use crate::mdict::{KeyNormalizer, Result};
+
+/// 查找词头时使用的模式, 是否区分大小写由 header 的 KeyCaseSensitive 决定
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub enum Pattern<'a> {
+    /// MDict 的通配符, `*` 匹配任意多个字符, `?` 匹配一个字符, 需要匹配整个词头.
+    /// 与查询词头时一样先规范化词头和通配符, StripKey 为 Yes 时忽略其中的空白和标点
+    Wildcard(&'a str),
+    /// 正则表达式, 匹配词典中原本的词头的一部分即可, 需要匹配整个词头时使用 `^` 和 `$`
+    Regex(&'a str),
+}
+
+/// 编译之后的模式
+pub(crate) struct Matcher {
+    regex: Regex,
+    /// 匹配的词头规范化之后都具有的前缀, 用于跳过不需要的 block
+    pub(crate) prefix: String,
+    /// 通配符匹配规范化之后的词头
+    normalizer: Option<KeyNormalizer>,
+}
+
+impl Matcher {
+    pub(crate) fn is_match(&self, key: &str) -> bool {
+        match &self.normalizer {
+            Some(normalizer) => self.regex.is_match(&normalizer.normalize(key)),
+            None => self.regex.is_match(key),
+        }
+    }
+}
+
+impl Pattern<'_> {
+    pub(crate) fn compile(&self, normalizer: &KeyNormalizer) -> Result<Matcher> {
+        let (regex, prefix, normalized) = match *self {
+            Pattern::Wildcard(pattern) => {
+                let mut regex = String::from("^(?:");
+                // 通配符之间的部分分别规范化, 以免去掉 `*` 和 `?`
+                let mut literal = String::new();
+                for c in pattern.chars() {
+                    if c != '*' && c != '?' {
+                        literal.push(c);
+                        continue;
+                    }
+                    regex.push_str(&regex::escape(&normalizer.normalize(&literal)));
+                    regex.push_str(if c == '*' { ".*" } else { "." });
+                    literal.clear();
+                }
+                regex.push_str(&regex::escape(&normalizer.normalize(&literal)));
+                regex.push_str(")$");
+
+                let prefix = pattern.split(['*', '?']).next().unwrap_or_default();
+                let prefix = normalizer.normalize(prefix).into_owned();
+                (regex, prefix, Some(*normalizer))
+            }
+            Pattern::Regex(pattern) => (pattern.to_string(), String::new(), None),
+        };
+
+        let regex = RegexBuilder::new(&regex)
+            .case_insensitive(!normalizer.case_sensitive)
+            .build()?;
+        Ok(Matcher {
+            regex,
+            prefix,
+            normalizer: normalized,
+        })
+    }
+}
@@ -0,0 +1,217 @@
+use std::{
+    collections::{BTreeMap, HashMap},
+    io::Write,
+    path::Path,
+};
+
+use byteorder::{LittleEndian, WriteBytesExt};
+use nom::{
+    combinator::map_res,
+    multi::{length_count, length_data},
+    number::complete::{le_u16, le_u32, le_u8},
+    sequence::tuple,
+    IResult,
+};
+
+use crate::fulltext::{is_cjk, load_file, save_file};
+use crate::html::{self, is_example, Tag, Token};
+use crate::mdict::Result;
+
+/// 索引文件的开头, 最后一个字节为格式的版本
+const MAGIC: &[u8] = b"MDRV\x01";
+
+// 中文释义的权重, 以百分比表示
+/// 义项的释义
+const HEADING: u32 = 100;
+/// 没有标记的文本
+const PLAIN: u32 = 70;
+/// 例句的译文中顺带出现的中文
+const EXAMPLE: u32 = 30;
+
+/// 从中文释义到英文词条的索引
+#[derive(Debug)]
+pub(crate) struct ReverseIndex {
+    fingerprint: u64,
+    // 词条所在的 block 和位置
+    docs: Vec<(u32, u32)>,
+    // 释义中连续的一段中文, 包含它的文档和权重
+    glosses: BTreeMap<String, Vec<(u32, u8)>>,
+}
+
+impl ReverseIndex {
+    pub(crate) fn new(fingerprint: u64) -> Self {
+        ReverseIndex {
+            fingerprint,
+            docs: Vec::new(),
+            glosses: BTreeMap::new(),
+        }
+    }
+
+    /// `html` 为词条原本的释义
+    pub(crate) fn add(&mut self, position: (usize, usize), html: &str) {
+        let doc = self.docs.len() as u32;
+        self.docs.push((position.0 as u32, position.1 as u32));
+
+        // 同一段中文出现多次时取最显著的一次
+        let mut weights: HashMap<String, u8> = HashMap::new();
+        for (gloss, weight) in glosses(html) {
+            let v = weights.entry(gloss).or_default();
+            *v = (*v).max(weight);
+        }
+        for (gloss, weight) in weights {
+            self.glosses.entry(gloss).or_default().push((doc, weight));
+        }
+    }
+
+    /// 释义中包含 `query` 的词条所在的 block 和位置, 按得分从高到低排列.
+    /// 得分为释义的权重乘以 `query` 在这段释义中所占的比例
+    pub(crate) fn search(&self, query: &str) -> Vec<((usize, usize), f32)> {
+        let len = query.chars().count() as f32;
+
+        let mut scores: HashMap<u32, f32> = HashMap::new();
+        for (gloss, posting) in self.glosses.iter().filter(|v| v.0.contains(query)) {
+            let ratio = len / gloss.chars().count() as f32;
+            for &(doc, weight) in posting {
+                let score = scores.entry(doc).or_default();
+                *score = score.max(weight as f32 / 100.0 * ratio);
+            }
+        }
+
+        let mut found = scores.into_iter().collect::<Vec<_>>();
+        found.sort_by(|a, b| b.1.total_cmp(&a.1).then(a.0.cmp(&b.0)));
+
+        found
+            .into_iter()
+            .map(|(doc, score)| {
+                let (block, index) = self.docs[doc as usize];
+                ((block as usize, index as usize), score)
+            })
+            .collect()
+    }
+
+    pub(crate) fn save(&self, path: &Path) -> Result<()> {
+        let mut body = Vec::new();
+        body.write_u32::<LittleEndian>(self.docs.len() as u32)?;
+        for &(block, index) in &self.docs {
+            body.write_u32::<LittleEndian>(block)?;
+            body.write_u32::<LittleEndian>(index)?;
+        }
+        body.write_u32::<LittleEndian>(self.glosses.len() as u32)?;
+        for (gloss, posting) in &self.glosses {
+            body.write_u16::<LittleEndian>(gloss.len() as u16)?;
+            body.write_all(gloss.as_bytes())?;
+            body.write_u32::<LittleEndian>(posting.len() as u32)?;
+            for &(doc, weight) in posting {
+                body.write_u32::<LittleEndian>(doc)?;
+                body.write_u8(weight)?;
+            }
+        }
+
+        save_file(path, MAGIC, self.fingerprint, &body)
+    }
+
+    pub(crate) fn load(path: &Path, fingerprint: u64) -> Result<Option<Self>> {
+        let body = match load_file(path, MAGIC, fingerprint)? {
+            Some(v) => v,
+            None => return Ok(None),
+        };
+
+        Ok(match index(&body) {
+            Ok((&[], (docs, glosses))) => Some(ReverseIndex {
+                fingerprint,
+                docs,
+                glosses: glosses.into_iter().collect(),
+            }),
+            _ => None,
+        })
+    }
+}
+
+type Docs = Vec<(u32, u32)>;
+type Glosses = Vec<(String, Vec<(u32, u8)>)>;
+
+fn index(in_: &[u8]) -> IResult<&[u8], (Docs, Glosses), ()> {
+    tuple((
+        length_count(le_u32, tuple((le_u32, le_u32))),
+        length_count(
+            le_u32,
+            tuple((
+                map_res(length_data(le_u16), |v: &[u8]| {
+                    String::from_utf8(v.to_vec())
+                }),
+                length_count(le_u32, tuple((le_u32, le_u8))),
+            )),
+        ),
+    ))(in_)
+}
+
+/// 释义中每一段连续的中文及其权重. 权重由所在元素的 class 决定, 越靠后的义项权重越低
+fn glosses(html: &str) -> Vec<(String, u8)> {
+    let mut glosses = Vec::new();
+    // 打开的元素和它的 class 表示的权重
+    let mut stack: Vec<(String, Option<u32>)> = Vec::new();
+    let mut gloss = String::new();
+
+    for token in html::tokens(html) {
+        let tag = match token {
+            Token::Text(v) => {
+                for c in html::decode(v).chars() {
+                    if is_cjk(c) {
+                        gloss.push(c);
+                    } else {
+                        flush(&mut gloss, &stack, &mut glosses);
+                    }
+                }
+                continue;
+            }
+            Token::Tag(tag) => tag,
+            Token::Comment(_) | Token::Raw(_) => continue,
+        };
+
+        flush(&mut gloss, &stack, &mut glosses);
+        if tag.closing {
+            if let Some(i) = stack.iter().rposition(|v| v.0 == tag.name) {
+                stack.truncate(i);
+            }
+        } else if tag.is_open() {
+            let weight = weight(&tag);
+            stack.push((tag.name, weight));
+        }
+    }
+    flush(&mut gloss, &stack, &mut glosses);
+
+    glosses
+}
+
+fn flush(gloss: &mut String, stack: &[(String, Option<u32>)], glosses: &mut Vec<(String, u8)>) {
+    if gloss.is_empty() {
+        return;
+    }
+
+    // 最内层有标记的元素决定权重, 第一段之后每一段依次递减
+    let weight = stack.iter().rev().find_map(|v| v.1).unwrap_or(PLAIN);
+    let weight = weight * 10 / (10 + glosses.len().min(20) as u32);
+    glosses.push((std::mem::take(gloss), weight as u8));
+}
+
+/// 根据 class 判断元素中的中文是义项的释义还是例句
+fn weight(tag: &Tag) -> Option<u32> {
+    let class = tag.attribute("class")?;
+    if is_example(class) {
+        Some(EXAMPLE)
+    } else if is_heading(class) {
+        Some(HEADING)
+    } else {
+        None
+    }
+}
+
+/// 元素是不是义项的释义
+fn is_heading(class: &str) -> bool {
+    class.to_ascii_lowercase().split_whitespace().any(|v| {
+        matches!(v, "cn" | "zh" | "chn")
+            || ["def", "trans", "gloss", "sense", "meaning"]
+                .iter()
+                .any(|h| v.contains(h))
+    })
+}
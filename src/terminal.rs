@@ -0,0 +1,427 @@
+use std::{
+    env,
+    io::{self, IsTerminal},
+};
+
+use html2text::render::text_renderer::{TaggedLine, TaggedLineElement, TextDecorator};
+
+use crate::dictionary::Entry;
+use crate::fulltext;
+use crate::html::{self, is_example, is_headword, Tag, Token};
+
+/// 在终端中显示词条的方式
+#[derive(Debug, Clone, PartialEq)]
+pub struct TerminalOptions {
+    /// 折行的宽度, 嵌套的列表和表格放不下时会适当加宽, 仍然放不下时只显示文本
+    pub width: usize,
+    /// 是否使用 ANSI 的粗体, 斜体和颜色
+    pub color: bool,
+}
+
+impl Default for TerminalOptions {
+    /// 宽度取自 `COLUMNS`, 默认为 80. 标准输出不是终端或者设置了 `NO_COLOR` 时不使用颜色
+    fn default() -> Self {
+        TerminalOptions {
+            width: env::var("COLUMNS")
+                .ok()
+                .and_then(|v| v.parse().ok())
+                .unwrap_or(80),
+            color: io::stdout().is_terminal() && env::var_os("NO_COLOR").is_none(),
+        }
+    }
+}
+
+// ANSI 的 SGR 参数
+const HEADWORD: &str = "1;33";
+const REDIRECT: &str = "2";
+const STRONG: &str = "1";
+const EMPHASIS: &str = "3";
+const PHONETIC: &str = "32";
+const EXAMPLE: &str = "3;34";
+const LINK: &str = "4;36";
+const STRIKEOUT: &str = "9";
+
+/// 例句的链接地址. html2text 没有自定义样式的办法, 例句借用这个地址的链接表示
+const EXAMPLE_URL: &str = "mdict:example";
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Default)]
+enum Style {
+    #[default]
+    Plain,
+    Strong,
+    Emphasis,
+    Phonetic,
+    Example,
+    Link,
+    Strikeout,
+}
+
+impl Style {
+    fn sgr(&self) -> Option<&'static str> {
+        match self {
+            Style::Plain => None,
+            Style::Strong => Some(STRONG),
+            Style::Emphasis => Some(EMPHASIS),
+            Style::Phonetic => Some(PHONETIC),
+            Style::Example => Some(EXAMPLE),
+            Style::Link => Some(LINK),
+            Style::Strikeout => Some(STRIKEOUT),
+        }
+    }
+}
+
+/// 只记录样式, 不在文本中加入 `*` 这样的标记
+struct Decorator;
+
+impl TextDecorator for Decorator {
+    type Annotation = Style;
+
+    fn decorate_link_start(&mut self, url: &str) -> (String, Style) {
+        if url == EXAMPLE_URL {
+            (String::new(), Style::Example)
+        } else {
+            (String::new(), Style::Link)
+        }
+    }
+
+    fn decorate_link_end(&mut self) -> String {
+        String::new()
+    }
+
+    fn decorate_em_start(&mut self) -> (String, Style) {
+        (String::new(), Style::Emphasis)
+    }
+
+    fn decorate_em_end(&mut self) -> String {
+        String::new()
+    }
+
+    fn decorate_strong_start(&mut self) -> (String, Style) {
+        (String::new(), Style::Strong)
+    }
+
+    fn decorate_strong_end(&mut self) -> String {
+        String::new()
+    }
+
+    fn decorate_strikeout_start(&mut self) -> (String, Style) {
+        (String::new(), Style::Strikeout)
+    }
+
+    fn decorate_strikeout_end(&mut self) -> String {
+        String::new()
+    }
+
+    fn decorate_code_start(&mut self) -> (String, Style) {
+        (String::new(), Style::Phonetic)
+    }
+
+    fn decorate_code_end(&mut self) -> String {
+        String::new()
+    }
+
+    fn decorate_preformat_first(&mut self) -> Style {
+        Style::Plain
+    }
+
+    fn decorate_preformat_cont(&mut self) -> Style {
+        Style::Plain
+    }
+
+    fn decorate_image(&mut self, title: &str) -> (String, Style) {
+        (title.to_string(), Style::Plain)
+    }
+
+    fn make_subblock_decorator(&self) -> Self {
+        Decorator
+    }
+
+    fn finalise(self) -> Vec<TaggedLine<Style>> {
+        Vec::new()
+    }
+}
+
+/// 删除线的组合字符, html2text 会把它加在删除线中的每个字符后面
+const COMBINING_STRIKEOUT: char = '\u{336}';
+
+/// 词头, 音标和例句对应的标签. html2text 只认识 `strong`, `em`, `code` 和链接这几种行内标签,
+/// 例句用指向 `EXAMPLE_URL` 的链接表示. 链接不能嵌套, `example` 为 `false` 时不再标记例句
+fn wrapper(tag: &Tag, example: bool) -> Option<&'static str> {
+    let class = tag.attribute("class").unwrap_or_default();
+    let classes = class.to_ascii_lowercase();
+    let classes = classes.split_whitespace().collect::<Vec<_>>();
+
+    if example && is_example(class) {
+        Some("a")
+    } else if classes.iter().any(|v| {
+        ["phon", "pron", "ipa", "symbol"]
+            .iter()
+            .any(|h| v.contains(h))
+    }) {
+        Some("code")
+    } else if tag.name == "b" || is_headword(class) {
+        Some("strong")
+    } else if tag.name == "i" {
+        Some("em")
+    } else {
+        None
+    }
+}
+
+fn open(wrapper: &str) -> String {
+    if wrapper == "a" {
+        format!("<a href=\"{}\">", EXAMPLE_URL)
+    } else {
+        format!("<{}>", wrapper)
+    }
+}
+
+/// 按标签和 class 在词头, 音标和例句的元素内部加上 html2text 认识的标签
+fn annotate(html: &str) -> String {
+    let mut text = String::with_capacity(html.len());
+    // 打开的元素和在它内部加上的标签
+    let mut stack: Vec<(String, Option<&str>)> = Vec::new();
+
+    for token in html::tokens(html) {
+        let tag = match token {
+            Token::Tag(tag) => tag,
+            Token::Text(v) | Token::Comment(v) | Token::Raw(v) => {
+                text.push_str(v);
+                continue;
+            }
+        };
+        // 例句中的链接前后暂时结束例句
+        let example = stack.iter().any(|v| v.1 == Some("a"));
+
+        if tag.closing {
+            let i = match stack.iter().rposition(|v| v.0 == tag.name) {
+                Some(v) => v,
+                None => {
+                    text.push_str(tag.text);
+                    continue;
+                }
+            };
+            for (_, wrapper) in stack.drain(i..).rev() {
+                if let Some(wrapper) = wrapper {
+                    text.push_str(&format!("</{}>", wrapper));
+                }
+            }
+            text.push_str(tag.text);
+            if tag.name == "a" && stack.iter().any(|v| v.1 == Some("a")) {
+                text.push_str(&open("a"));
+            }
+        } else if tag.name == "a" && example {
+            text.push_str("</a>");
+            text.push_str(tag.text);
+            if tag.is_open() {
+                stack.push((tag.name, None));
+            } else {
+                text.push_str(&open("a"));
+            }
+        } else {
+            text.push_str(tag.text);
+            if tag.is_open() {
+                let link = example || stack.iter().any(|v| v.0 == "a");
+                let wrapper = wrapper(&tag, !link && tag.name != "a");
+                if let Some(wrapper) = wrapper {
+                    text.push_str(&open(wrapper));
+                }
+                stack.push((tag.name, wrapper));
+            }
+        }
+    }
+
+    text
+}
+
+/// html2text 给表格的每一列至少要分配的宽度, 去掉边框之后能放下一个全角字符
+const MIN_COLUMN: usize = 3;
+
+/// 计算最小宽度时打开的元素
+#[derive(Default)]
+struct Block {
+    name: String,
+    // 内容需要的宽度
+    width: usize,
+    // `ol` 中列表项的个数, `tr` 和 `table` 中的列数
+    count: usize,
+    // 单元格跨越的列数
+    colspan: usize,
+    // `ol` 的第一个序号
+    start: i64,
+}
+
+/// html2text 在列表, 引用和标题中按固定的宽度缩进, 宽度不够时会出错或者不停地折行.
+/// 返回按嵌套的层数计算的最小宽度, 最内层至少能放下一个全角字符.
+/// 表格的单元格中有缩进的内容时, 各列分到的宽度无法预先知道, 返回 `None`
+fn min_width(html: &str) -> Option<usize> {
+    let mut stack = vec![Block {
+        width: 1,
+        ..Default::default()
+    }];
+    let mut fits = true;
+
+    for token in html::tokens(html) {
+        let tag = match token {
+            Token::Text(v) => {
+                if !html::decode(v).trim().is_empty() {
+                    let top = stack.last_mut().unwrap();
+                    top.width = top.width.max(2);
+                }
+                continue;
+            }
+            Token::Tag(tag) => tag,
+            Token::Comment(_) | Token::Raw(_) => continue,
+        };
+
+        if tag.closing {
+            // 不会结束最外层
+            if let Some(i) = stack
+                .iter()
+                .rposition(|v| v.name == tag.name)
+                .filter(|v| *v > 0)
+            {
+                while stack.len() > i {
+                    fits &= close(&mut stack);
+                }
+            }
+            continue;
+        } else if !tag.is_open() {
+            continue;
+        }
+
+        let block = Block {
+            colspan: tag
+                .attribute("colspan")
+                .and_then(|v| v.parse().ok())
+                .unwrap_or(1usize)
+                .max(1),
+            start: tag
+                .attribute("start")
+                .and_then(|v| v.parse().ok())
+                .unwrap_or(1),
+            ..Default::default()
+        };
+        let parent = match tag.name.as_str() {
+            "li" => "ol",
+            "td" | "th" => "tr",
+            _ => "",
+        };
+        if let Some(parent) = stack.iter_mut().rev().find(|v| v.name == parent) {
+            let span = if parent.name == "tr" {
+                block.colspan
+            } else {
+                1
+            };
+            parent.count = parent.count.saturating_add(span);
+        }
+        stack.push(Block {
+            name: tag.name,
+            ..block
+        });
+    }
+    while stack.len() > 1 {
+        fits &= close(&mut stack);
+    }
+
+    Some(stack[0].width).filter(|_| fits)
+}
+
+/// 结束最内层的元素, 把它需要的宽度加到外层的元素上. 单元格中有缩进的内容时返回 `false`
+fn close(stack: &mut Vec<Block>) -> bool {
+    let block = stack.pop().unwrap();
+    let table = stack.iter().rposition(|v| v.name == "table");
+
+    let width = match block.name.as_str() {
+        // 缩进之后至少还要有一列, 即使没有内容
+        "ul" | "blockquote" | "dd" => block.width.max(1) + 2,
+        // 序号和后面的 `. `
+        "ol" => {
+            let last = block.start.saturating_add(block.count.max(1) as i64 - 1);
+            let digits = block.start.to_string().len().max(last.to_string().len());
+            block.width.max(1) + digits + 2
+        }
+        // 开头的 `#` 和空格
+        "h1" | "h2" | "h3" | "h4" => block.width + block.name[1..].parse::<usize>().unwrap() + 1,
+        // 单元格中只有文本时, 表格按列数计算宽度
+        "td" | "th" if table.is_some() => {
+            if block.width > 2 {
+                return false;
+            }
+            0
+        }
+        "tr" if table.is_some() => {
+            let table = &mut stack[table.unwrap()];
+            table.count = table.count.max(block.count);
+            0
+        }
+        "table" => block.count.saturating_mul(MIN_COLUMN),
+        _ => block.width,
+    };
+
+    let parent = stack.last_mut().unwrap();
+    parent.width = parent.width.max(width);
+    true
+}
+
+fn paint(text: &mut String, s: &str, sgr: Option<&str>, color: bool) {
+    match sgr {
+        Some(sgr) if color && !s.trim().is_empty() => {
+            text.push_str(&format!("\x1b[{}m{}\x1b[0m", sgr, s));
+        }
+        _ => text.push_str(s),
+    }
+}
+
+impl Entry {
+    /// 渲染为在终端中显示的文本, 按 `options.width` 折行, 不显示脚本和样式.
+    /// 第一行为词头, 经过 `@@@LINK=` 跳转时附上跳转的过程
+    pub fn to_terminal(&self, options: &TerminalOptions) -> String {
+        let mut text = String::new();
+        paint(&mut text, &self.key, Some(HEADWORD), options.color);
+        if !self.redirects.is_empty() {
+            text.push_str("  ");
+            paint(&mut text, &self.chain(), Some(REDIRECT), options.color);
+        }
+        text.push('\n');
+
+        // 嵌套得太深, 或者表格中有列表时, 只显示去掉标签之后的文本
+        let lines = match min_width(&self.definition) {
+            Some(need) if need <= options.width.max(2) * 2 => {
+                html2text::parse(annotate(&self.definition).as_bytes())
+                    .render(options.width.max(need), Decorator)
+                    .into_lines()
+            }
+            _ => {
+                let text = fulltext::strip_html(&self.definition)
+                    .replace('&', "&amp;")
+                    .replace('<', "&lt;");
+                html2text::parse(format!("<p>{}</p>", text).as_bytes())
+                    .render(options.width.max(2), Decorator)
+                    .into_lines()
+            }
+        };
+        for line in lines {
+            for element in line.iter() {
+                if let TaggedLineElement::Str(v) = element {
+                    // 嵌套的样式叠加在一起
+                    let sgr = v.tag.iter().filter_map(|v| v.sgr()).collect::<Vec<_>>();
+                    let sgr = Some(sgr.join(";")).filter(|v| !v.is_empty());
+                    // 使用颜色时用 ANSI 的删除线代替组合字符
+                    if options.color && v.tag.contains(&Style::Strikeout) {
+                        let s = v.s.replace(COMBINING_STRIKEOUT, "");
+                        paint(&mut text, &s, sgr.as_deref(), true);
+                    } else {
+                        paint(&mut text, &v.s, sgr.as_deref(), options.color);
+                    }
+                }
+            }
+            text.push('\n');
+        }
+
+        let len = text.trim_end().len();
+        text.truncate(len);
+        text.push('\n');
+        text
+    }
+}
@@ -0,0 +1,204 @@
+//! 损坏的文件应当返回带有位置的错误, 不能 panic
+
+use mdict_test::{Checksum, Dictionary, Error, ParseOptions, Section};
+
+const V1: &[u8] = include_bytes!("data/small.v1.mdx");
+const V2: &[u8] = include_bytes!("data/small.v2.mdx");
+const V3: &[u8] = include_bytes!("data/small.v3.mdx");
+// block 没有压缩, key info 没有加密
+const RAW: &[u8] = include_bytes!("data/raw.v2.mdx");
+
+fn be(file: &[u8], offset: usize, width: usize) -> u64 {
+    file[offset..offset + width]
+        .iter()
+        .fold(0, |v, b| v << 8 | *b as u64)
+}
+
+fn set(file: &mut [u8], offset: usize, width: usize, value: u64) {
+    file[offset..offset + width].copy_from_slice(&value.to_be_bytes()[8 - width..]);
+}
+
+/// header 之后的位置
+fn after_header(file: &[u8]) -> usize {
+    4 + be(file, 0, 4) as usize + 4
+}
+
+/// 1.x 和 2.0 的 key block header 以及 record block header 的位置
+fn headers(file: &[u8], width: usize) -> (usize, usize) {
+    let key_header = after_header(file);
+    // 2.0 的 key block header 多了解压后的大小和 checksum
+    let (nb_block_info, info) = if width == 8 {
+        (key_header + 24, key_header + 44)
+    } else {
+        (key_header + 8, key_header + 16)
+    };
+    let nb_blocks = nb_block_info + width;
+    let record_header =
+        info + be(file, nb_block_info, width) as usize + be(file, nb_blocks, width) as usize;
+    (key_header, record_header)
+}
+
+fn open(file: &[u8], lazy: bool) -> Result<Dictionary, Error> {
+    let options = ParseOptions {
+        lazy,
+        ..ParseOptions::default()
+    };
+    Dictionary::from_bytes_with(file, &options)
+}
+
+fn assert_located(result: Result<Dictionary, Error>) {
+    match result {
+        Err(Error::Parse { .. }) => {}
+        Err(e) => panic!("error without location: {}", e),
+        Ok(_) => panic!("corrupted file opened"),
+    }
+}
+
+#[test]
+fn fixtures_open() {
+    for file in [V1, V2, V3] {
+        let dict = open(file, false).unwrap();
+        assert_eq!(dict.lookup("cherry").unwrap()[0].key, "cherry");
+    }
+}
+
+#[test]
+fn record_block_count() {
+    let mut file = V2.to_vec();
+    let (_, record_header) = headers(&file, 8);
+    set(&mut file, record_header, 8, 1 << 61);
+
+    assert_located(open(&file, false));
+    assert_located(open(&file, true));
+}
+
+#[test]
+fn key_block_count() {
+    // 1.x 的 key block header 没有 checksum
+    let mut file = V1.to_vec();
+    let (key_header, _) = headers(&file, 4);
+    set(&mut file, key_header, 4, u32::MAX as u64);
+
+    assert_located(open(&file, true));
+}
+
+#[test]
+fn v3_block_count() {
+    let mut file = V3.to_vec();
+    // 每个 section 为 type, 长度和数据, block 的数量在数据的开头
+    let mut offset = after_header(&file);
+    while offset < file.len() {
+        set(&mut file, offset + 12, 4, u32::MAX as u64);
+        offset += 12 + be(&file, offset + 4, 8) as usize;
+    }
+
+    assert_located(open(&file, true));
+}
+
+#[test]
+fn decompressed_size() {
+    let mut file = V2.to_vec();
+    let (_, record_header) = headers(&file, 8);
+    // 第一个 record block 的解压后大小
+    set(&mut file, record_header + 32 + 8, 8, u64::MAX >> 4);
+
+    assert_located(open(&file, false));
+}
+
+#[test]
+fn byte_flips() {
+    for file in [V1, V2, V3] {
+        for offset in 0..file.len() {
+            for flip in [0x01, 0x80, 0xff] {
+                let mut file = file.to_vec();
+                file[offset] ^= flip;
+                for lazy in [false, true] {
+                    if let Ok(dict) = open(&file, lazy) {
+                        for key in dict.keys().filter_map(|v| v.ok()) {
+                            let _ = dict.lookup(key);
+                        }
+                    }
+                }
+            }
+        }
+    }
+}
+
+#[test]
+fn block_size_overflow() {
+    let mut file = V2.to_vec();
+    let (_, record_header) = headers(&file, 8);
+    // 第一个 record block 压缩后的大小
+    set(&mut file, record_header + 32, 8, u64::MAX - 3);
+
+    assert_located(open(&file, true));
+}
+
+#[test]
+fn block_size_mismatch() {
+    let mut file = V2.to_vec();
+    let (_, record_header) = headers(&file, 8);
+    let nb_compressed = be(&file, record_header + 32, 8);
+    set(&mut file, record_header + 32, 8, nb_compressed + 1);
+
+    assert_located(open(&file, true));
+}
+
+fn find(file: &[u8], text: &[u8]) -> usize {
+    file.windows(text.len()).position(|v| v == text).unwrap()
+}
+
+/// 读取 record 时的错误带有所在 record block 的位置
+fn assert_record_error(file: &[u8]) {
+    let options = ParseOptions {
+        checksum: Checksum::Warn,
+        ..ParseOptions::default()
+    };
+    let dict = Dictionary::from_bytes_with(file, &options).unwrap();
+    match dict.lookup("cherry") {
+        Err(Error::Parse { location, .. }) => {
+            assert_eq!(location.section, Section::RecordBlock);
+            assert!(location.index.is_some());
+        }
+        Err(e) => panic!("error without location: {}", e),
+        Ok(_) => panic!("corrupted record read"),
+    }
+}
+
+#[test]
+fn record_offset() {
+    let mut file = RAW.to_vec();
+    // key block 中 key 之前是 record 的 offset
+    let key = find(&file, b"cherry\0");
+    set(&mut file, key - 8, 8, 1 << 40);
+
+    assert_record_error(&file);
+}
+
+#[test]
+fn record_encoding() {
+    let mut file = RAW.to_vec();
+    let record = find(&file, b"a small red fruit");
+    file[record] = 0xff;
+
+    assert_record_error(&file);
+}
+
+#[test]
+fn v3_key_index() {
+    let mut file = V3.to_vec();
+    // 找到 key data, 改动第二个 key block 的内容
+    let mut offset = after_header(&file);
+    while be(&file, offset, 4) != 0x0300_0000 {
+        offset += 12 + be(&file, offset + 4, 8) as usize;
+    }
+    let first = offset + 12 + 12;
+    let second = first + 8 + be(&file, first + 4, 4) as usize;
+    file[second + 8 + 8] ^= 0xff;
+
+    // 有 key index 时查找其他 block 中的 key 不需要解压这个 block
+    let dict = open(&file, true).unwrap();
+    assert_eq!(dict.lookup("apple").unwrap()[0].key, "apple");
+    assert_eq!(dict.lookup("fig").unwrap()[0].key, "fig");
+    assert!(dict.lookup("cherry").is_err());
+}
@@ -0,0 +1,38 @@
+//! 用注册码加密的词典, key header 用注册码和 email 得到的 key 加密
+
+use mdict_test::{Dictionary, Error, ParseOptions, RegCode};
+
+const ENCRYPTED: &[u8] = include_bytes!("data/encrypted.v2.mdx");
+
+fn open(code: Option<&str>) -> Result<Dictionary, Error> {
+    let options = ParseOptions {
+        reg_code: code.map(|code| RegCode {
+            code: code.to_string(),
+            user_id: "user@example.com".to_string(),
+        }),
+        ..ParseOptions::default()
+    };
+    Dictionary::from_bytes_with(ENCRYPTED, &options)
+}
+
+#[test]
+fn reg_code() {
+    let dict = open(Some("0123456789ABCDEF0123456789abcdef ")).unwrap();
+    assert_eq!(dict.lookup("cherry").unwrap()[0].key, "cherry");
+}
+
+#[test]
+fn missing_reg_code() {
+    assert!(matches!(open(None), Err(Error::Encrypted)));
+}
+
+#[test]
+fn invalid_reg_code() {
+    assert!(matches!(open(Some("0123")), Err(Error::InvalidRegCode)));
+    assert!(matches!(
+        open(Some("0123456789abcdef0123456789abcdeg")),
+        Err(Error::InvalidRegCode)
+    ));
+    // 错误的注册码解密出的 key header 无法通过校验
+    assert!(open(Some("fedcba9876543210fedcba9876543210")).is_err());
+}
@@ -0,0 +1,68 @@
+use base64::{engine::general_purpose::STANDARD, Engine};
+use mdict_test::{Dictionary, Export};
+
+/// 导出 `apple` 和 `pear`, 资源在 `res.mdd` 和 `res.1.mdd` 中
+fn export() -> Export {
+    let dict =
+        Dictionary::open(concat!(env!("CARGO_MANIFEST_DIR"), "/tests/data/res.mdx")).unwrap();
+    let mut entries = dict.lookup("apple").unwrap();
+    entries.extend(dict.lookup("pear").unwrap());
+    dict.export_html(&entries).unwrap()
+}
+
+fn data_uri(mime: &str, data: &[u8]) -> String {
+    format!("data:{};base64,{}", mime, STANDARD.encode(data))
+}
+
+#[test]
+fn inlines_resources() {
+    let html = export().html;
+    assert!(html.contains(&format!(r#"<img src="{}">"#, data_uri("image/png", b"SPK"))));
+    assert!(html.contains(&format!(r#"<a href="{}">"#, data_uri("audio/mpeg", b"MP3"))));
+    // 第二个 mdd 中的资源
+    assert!(html.contains(&format!(r#"<img src="{}">"#, data_uri("image/gif", b"GIF"))));
+    // 行内样式和 `<style>` 中的 `url()`
+    assert!(html.contains(&format!(
+        r#"style="background:url(&quot;{}&quot;)""#,
+        data_uri("image/png", b"SPK")
+    )));
+    assert!(html.contains(&format!(
+        r#"background:url("{}")"#,
+        data_uri("image/png", b"PNGDATA")
+    )));
+}
+
+#[test]
+fn inlines_style_sheet_urls() {
+    let html = export().html;
+    let start = html.find("data:text/css;base64,").unwrap() + 21;
+    let end = start + html[start..].find('"').unwrap();
+    let css = String::from_utf8(STANDARD.decode(&html[start..end]).unwrap()).unwrap();
+
+    // 样式表中的路径相对于样式表所在的目录, 去掉查询参数
+    assert!(css.contains(&format!(r#"url("{}")"#, data_uri("image/png", b"PNGDATA"))));
+    assert!(css.contains(&format!(r#"url("{}")"#, data_uri("font/woff", b"WOFF"))));
+    // 已经是 data URI 的和找不到的保持原样
+    assert!(css.contains("url(data:image/png;base64,AA==)"));
+    assert!(css.contains("url(../img/nope.png)"));
+}
+
+#[test]
+fn entry_links() {
+    let html = export().html;
+    assert!(html.contains(r##"<article id="apple">"##));
+    assert!(html.contains(r##"<a href="#pear">pear</a>"##));
+    assert!(html.contains(r##"<a href="#top">top</a>"##));
+    // 没有导出的词条保持原样
+    assert!(html.contains(r#"<a href="entry://plum">plum</a>"#));
+}
+
+#[test]
+fn missing_resources() {
+    let export = export();
+    assert_eq!(
+        export.missing,
+        ["img/nope.png", "img/missing one.png", "js/x.js"]
+    );
+    assert!(export.html.contains(r#"<img src="img/missing%20one.png">"#));
+}
@@ -0,0 +1,38 @@
+use mdict_test::{Dictionary, Distance};
+
+const V2: &[u8] = include_bytes!("data/small.v2.mdx");
+
+#[test]
+fn damerau_counts_transpositions() {
+    let dict = Dictionary::from_bytes(V2).unwrap();
+    assert_eq!(
+        dict.fuzzy("cehrry", Distance::Damerau(1), 10).unwrap(),
+        [("cherry", 1)]
+    );
+    assert!(dict
+        .fuzzy("cehrry", Distance::Levenshtein(1), 10)
+        .unwrap()
+        .is_empty());
+    assert_eq!(
+        dict.fuzzy("cehrry", Distance::Levenshtein(2), 10).unwrap(),
+        [("cherry", 2)]
+    );
+}
+
+#[test]
+fn ordered_by_distance() {
+    let dict = Dictionary::from_bytes(V2).unwrap();
+    // 与查询词头一样忽略大小写
+    assert_eq!(
+        dict.fuzzy("DTAE", Distance::Damerau(1), 10).unwrap(),
+        [("date", 1)]
+    );
+    assert_eq!(
+        dict.fuzzy("dig", Distance::Damerau(3), 10).unwrap(),
+        [("fig", 1), ("date", 3)]
+    );
+    assert_eq!(
+        dict.fuzzy("dig", Distance::Damerau(3), 1).unwrap(),
+        [("fig", 1)]
+    );
+}
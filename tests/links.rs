@@ -0,0 +1,16 @@
+use mdict_test::LinkOptions;
+
+#[test]
+fn rewrites_href_and_src() {
+    let html = r#"<a href="entry://pear tree#s1">pear</a><a href=sound://uk/a.spx>a</a><img src="\img\x.png">"#;
+    assert_eq!(
+        LinkOptions::default().rewrite(html),
+        r#"<a href="/lookup/pear%20tree#s1">pear</a><a href="/res/uk/a.spx">a</a><img src="/res/img/x.png">"#
+    );
+}
+
+#[test]
+fn other_attributes_unchanged() {
+    let html = r#"<img alt="entry://q" data-src="sound://z" title="img/x.png">"#;
+    assert_eq!(LinkOptions::default().rewrite(html), html);
+}
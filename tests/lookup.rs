@@ -0,0 +1,81 @@
+use mdict_test::{Dictionary, Pattern};
+
+const LOOKUP: &[u8] = include_bytes!("data/lookup.mdx");
+
+fn keys(dict: &Dictionary, query: &str) -> Vec<(String, Option<String>)> {
+    dict.lookup(query)
+        .unwrap()
+        .into_iter()
+        .map(|v| (v.key, v.lemma))
+        .collect()
+}
+
+#[test]
+fn lemma_stops_at_first_tier() {
+    let dict = Dictionary::from_bytes(LOOKUP).unwrap();
+    // `bet` 也可以按 -er 和重复的辅音还原得到, 但不规则变化已经找到了结果
+    assert_eq!(
+        keys(&dict, "better"),
+        [
+            ("good".to_string(), Some("good".to_string())),
+            ("well".to_string(), Some("well".to_string())),
+        ]
+    );
+    assert_eq!(
+        keys(&dict, "running"),
+        [("run".to_string(), Some("run".to_string()))]
+    );
+}
+
+#[test]
+fn silent_e_comes_first() {
+    let dict = Dictionary::from_bytes(LOOKUP).unwrap();
+    // 词典中同时有 `us`, `hop` 和 `not`
+    for (query, lemma) in [("using", "use"), ("hoped", "hope"), ("notes", "note")] {
+        assert_eq!(
+            keys(&dict, query),
+            [(lemma.to_string(), Some(lemma.to_string()))]
+        );
+    }
+}
+
+#[test]
+fn redirect_loop_keeps_homographs() {
+    let dict = Dictionary::from_bytes(LOOKUP).unwrap();
+    let entries = dict.lookup("bank").unwrap();
+    assert_eq!(entries.len(), 2);
+    assert_eq!(
+        entries[0].definition.trim(),
+        "bank: a financial institution"
+    );
+    // 循环的链接返回链接本身以及经过的词头
+    assert_eq!(entries[1].chain(), "bank → loop → loop2");
+    assert_eq!(entries[1].definition.trim(), "@@@LINK=loop");
+}
+
+#[test]
+fn too_many_redirects() {
+    let mut dict = Dictionary::from_bytes(LOOKUP).unwrap();
+    dict.set_max_redirects(1);
+    let entries = dict.lookup("bank").unwrap();
+    assert_eq!(entries[1].chain(), "bank → loop");
+    assert_eq!(entries[1].definition.trim(), "@@@LINK=loop2");
+}
+
+#[test]
+fn wildcard_strips_key() {
+    let dict = Dictionary::from_bytes(LOOKUP).unwrap();
+    // StripKey 为 Yes, 通配符与词头一样忽略标点和大小写
+    assert_eq!(
+        dict.search_page(Pattern::Wildcard("ba-n*"), 0, 10).unwrap(),
+        ["bank"]
+    );
+    assert_eq!(
+        dict.search_page(Pattern::Wildcard("B?N K"), 0, 10).unwrap(),
+        ["bank"]
+    );
+    assert_eq!(
+        dict.search_page(Pattern::Regex("^ba-n"), 0, 10).unwrap(),
+        Vec::<&str>::new()
+    );
+}
@@ -0,0 +1,69 @@
+use mdict_test::Entry;
+
+fn render(html: &str) -> String {
+    let entry = Entry {
+        key: "word".to_string(),
+        definition: html.to_string(),
+        redirects: Vec::new(),
+        lemma: None,
+    };
+    entry.to_markdown()
+}
+
+#[test]
+fn senses_and_examples() {
+    let html =
+        r#"<ol><li>a fruit<div class="ex">An <b>apple</b> a day.</div></li><li>a tree</li></ol>"#;
+    assert_eq!(
+        render(html),
+        "**word**\n\n1. a fruit\n\n   > An **apple** a day.\n\n2. a tree\n"
+    );
+}
+
+#[test]
+fn links() {
+    let html = concat!(
+        r#"<a href="entry://red apple#x">red</a> <a href="https://x.org/a b">site</a> "#,
+        r#"<a href="sound://a.mp3">play</a>"#,
+    );
+    assert_eq!(
+        render(html),
+        "**word**\n\n[red](red%20apple#x) [site](https://x.org/a%20b) play\n"
+    );
+}
+
+#[test]
+fn escapes() {
+    let html = "<p>1. a_b [c] &lt;tag&gt;</p><p>- item</p>";
+    assert_eq!(
+        render(html),
+        "**word**\n\n1\\. a\\_b \\[c\\] \\<tag\\>\n\n\\- item\n"
+    );
+}
+
+#[test]
+fn breaks_headings_and_nested_lists() {
+    let html = "a<br>b<br><b>c </b>d<h2>Head</h2><ul><li>x<ul><li>y</li></ul></li></ul>";
+    assert_eq!(
+        render(html),
+        "**word**\n\na\\\nb\\\n**c** d\n\n**Head**\n\n- x\n\n  - y\n"
+    );
+}
+
+#[test]
+fn redirects() {
+    let entry = Entry {
+        key: "b".to_string(),
+        definition: "<i>x</i>".to_string(),
+        redirects: vec!["a".to_string()],
+        lemma: None,
+    };
+    assert_eq!(entry.to_markdown(), "**b** *a → b*\n\n*x*\n");
+}
+
+#[test]
+fn large_list_start() {
+    let text = render(r#"<ol start="4294967295"><li>a</li><li>b</li></ol>"#);
+    assert!(text.contains("4294967295. a"), "{}", text);
+    assert!(text.contains("4294967295. b"), "{}", text);
+}
@@ -0,0 +1,39 @@
+use mdict_test::{mdict::mdd::Mdd, ParseOptions};
+
+const MDD: &[u8] = include_bytes!("data/res.mdd");
+
+#[test]
+fn resources() {
+    let mdd = Mdd::from_bytes(MDD, &ParseOptions::default()).unwrap();
+    assert_eq!(
+        mdd.resource("\\img\\bg.png").unwrap().as_deref(),
+        Some(&b"PNGDATA"[..])
+    );
+    // 也接受 `/` 分隔和没有开头的分隔符的写法, 大小写由 KeyCaseSensitive 决定
+    assert_eq!(
+        mdd.resource("img/bg.png").unwrap().as_deref(),
+        Some(&b"PNGDATA"[..])
+    );
+    assert_eq!(
+        mdd.resource("/FONTS/F.WOFF").unwrap().as_deref(),
+        Some(&b"WOFF"[..])
+    );
+    assert_eq!(mdd.resource("img/nope.png").unwrap(), None);
+}
+
+#[test]
+fn keys() {
+    let mdd = Mdd::from_bytes(MDD, &ParseOptions::default()).unwrap();
+    let mut keys = mdd.keys().collect::<Result<Vec<_>, _>>().unwrap();
+    keys.sort_unstable();
+    assert_eq!(
+        keys,
+        [
+            "\\css\\oald.css",
+            "\\fonts\\f.woff",
+            "\\img\\bg.png",
+            "\\img\\spk.png",
+            "\\uk\\apple.mp3"
+        ]
+    );
+}
@@ -0,0 +1,64 @@
+use mdict_test::{Entry, TerminalOptions};
+
+fn render(html: &str, width: usize, color: bool) -> String {
+    let entry = Entry {
+        key: "word".to_string(),
+        definition: html.to_string(),
+        redirects: Vec::new(),
+        lemma: None,
+    };
+    entry.to_terminal(&TerminalOptions { width, color })
+}
+
+#[test]
+fn strikeout_survives() {
+    let html = r#"<s>old</s> <span class="ex">An example.</span>"#;
+    assert_eq!(
+        render(html, 40, false),
+        "word\no\u{336}l\u{336}d\u{336} An example.\n"
+    );
+    assert_eq!(
+        render(html, 40, true),
+        "\x1b[1;33mword\x1b[0m\n\x1b[9mold\x1b[0m \x1b[3;34mAn example.\x1b[0m\n"
+    );
+}
+
+#[test]
+fn links_in_examples() {
+    let html = r#"<div class="ex">See <a href="entry://it">it</a> here.</div>"#;
+    assert_eq!(
+        render(html, 40, true),
+        "\x1b[1;33mword\x1b[0m\n\x1b[3;34mSee \x1b[0m\x1b[4;36mit\x1b[0m\x1b[3;34m here.\x1b[0m\n"
+    );
+}
+
+#[test]
+fn narrow_width() {
+    let html = concat!(
+        "<ul><li><blockquote><ol start=\"99\"><li>中文</li><li>x</li></ol></blockquote></li></ul>",
+        "<dl><dt>t</dt><dd><dl><dt>t</dt><dd></dd></dl></dd></dl>",
+        "<h4><ul><li>中文</li></ul></h4>",
+        "<table><tr><td>a long cell</td><td colspan=\"2\"><ul><li><ul><li>中文</li></ul></li></ul></td></tr></table>",
+    );
+    for width in 0..12 {
+        let text = render(html, width, false);
+        assert_eq!(text.matches('中').count(), 3, "{}", text);
+    }
+}
+
+#[test]
+fn table_falls_back_to_text() {
+    // 单元格中有列表时各列的宽度无法预先知道, 不能为了放下表格无限地加宽
+    let long = "word ".repeat(360);
+    let html = format!(
+        "<table><tr><td>{}</td><td><ul><li><ul><li>中文</li></ul></li></ul></td></tr></table>",
+        long
+    );
+    let text = render(&html, 80, false);
+    assert!(text.lines().all(|v| v.chars().count() <= 80), "{}", text);
+    assert!(text.contains("中文"));
+
+    // 只有文本的表格照常显示
+    let text = render("<table><tr><td>a</td><td>b</td></tr></table>", 80, false);
+    assert!(text.contains('│') || text.contains('|'), "{}", text);
+}
@@ -0,0 +1,98 @@
+//! 不影响使用词典的错误交给 `ParseOptions::on_warning`, 不输出到标准错误
+
+use std::{
+    fs,
+    sync::{Arc, Mutex},
+};
+
+use mdict_test::{Checksum, Dictionary, ParseOptions, WarningHandler};
+
+const V2: &[u8] = include_bytes!("data/small.v2.mdx");
+// key info 没有加密, 改动 checksum 不影响解密
+const RAW: &[u8] = include_bytes!("data/raw.v2.mdx");
+
+fn be(file: &[u8], offset: usize, width: usize) -> u64 {
+    file[offset..offset + width]
+        .iter()
+        .fold(0, |v, b| v << 8 | *b as u64)
+}
+
+/// 把警告的内容收集起来的 `ParseOptions`
+fn collect(checksum: Checksum) -> (ParseOptions, Arc<Mutex<Vec<String>>>) {
+    let warnings = Arc::new(Mutex::new(Vec::new()));
+    let sink = warnings.clone();
+    let options = ParseOptions {
+        checksum,
+        on_warning: Some(WarningHandler::new(move |e| {
+            sink.lock().unwrap().push(e.to_string())
+        })),
+        ..ParseOptions::default()
+    };
+    (options, warnings)
+}
+
+#[test]
+fn checksum_warning() {
+    let mut file = V2.to_vec();
+    // 第一个 record block 的 checksum, 在 block 的类型之后
+    let record_header = {
+        let key_header = 4 + be(&file, 0, 4) as usize + 4;
+        let info = key_header + 44;
+        info + be(&file, key_header + 24, 8) as usize + be(&file, key_header + 32, 8) as usize
+    };
+    let block = record_header + 32 + 16 * be(&file, record_header, 8) as usize;
+    file[block + 4] ^= 0xff;
+
+    let (options, warnings) = collect(Checksum::Strict);
+    assert!(Dictionary::from_bytes_with(&file, &options).is_err());
+    assert!(warnings.lock().unwrap().is_empty());
+
+    let (options, warnings) = collect(Checksum::Warn);
+    let dict = Dictionary::from_bytes_with(&file, &options).unwrap();
+    assert_eq!(dict.lookup("cherry").unwrap()[0].key, "cherry");
+    let warnings = warnings.lock().unwrap();
+    assert_eq!(warnings.len(), 1);
+    assert!(warnings[0].starts_with("record-block block 0"));
+    assert!(warnings[0].ends_with("checksum mismatch"));
+}
+
+#[test]
+fn key_info_checksum() {
+    let mut file = RAW.to_vec();
+    // key block info 的 checksum, 在 key block header 和 block 的类型之后
+    let info = 4 + be(&file, 0, 4) as usize + 4 + 44;
+    file[info + 4] ^= 0xff;
+
+    let (options, warnings) = collect(Checksum::Strict);
+    assert!(Dictionary::from_bytes_with(&file, &options).is_err());
+    assert!(warnings.lock().unwrap().is_empty());
+
+    let (options, warnings) = collect(Checksum::Warn);
+    let dict = Dictionary::from_bytes_with(&file, &options).unwrap();
+    assert_eq!(dict.lookup("cherry").unwrap()[0].key, "cherry");
+    let warnings = warnings.lock().unwrap();
+    assert_eq!(warnings.len(), 1);
+    assert!(warnings[0].starts_with("key-info at offset"));
+    assert!(warnings[0].ends_with("checksum mismatch"));
+}
+
+#[test]
+fn save_warning() {
+    let dir = std::env::temp_dir().join(format!("mdict-warnings-{}", std::process::id()));
+    let path = dir.join("small.mdx");
+    fs::create_dir_all(&dir).unwrap();
+    fs::write(&path, V2).unwrap();
+    // 临时文件的位置被目录占据, 索引无法保存
+    fs::create_dir_all(dir.join("small.mdx.fts.tmp")).unwrap();
+
+    let (options, warnings) = collect(Checksum::Strict);
+    let dict = Dictionary::open_with(&path, &options).unwrap();
+    let found = dict.full_text("palm", 10).map(|v| v.len());
+    fs::remove_dir_all(&dir).unwrap();
+
+    assert_eq!(found.unwrap(), 1);
+    let warnings = warnings.lock().unwrap();
+    assert_eq!(warnings.len(), 1);
+    assert!(warnings[0].starts_with("failed to save"));
+    assert!(warnings[0].contains("small.mdx.fts:"));
+}